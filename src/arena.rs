@@ -0,0 +1,289 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use rand::prelude::*;
+use rand::rngs::SmallRng;
+use crate::expecti_min_max::Moves;
+use crate::game_controller::{Player as Side, State, Status};
+use crate::{expecti_min_max, mcts, min_max};
+use crate::min_max::Player;
+
+/// Picks the moves of one side in the arena.
+pub trait Engine<S> {
+    /// Plays a move for the side to move in `state`, which is not over. Chance after the move, like the dice of
+    /// Knucklebones, is left to the arena.
+    fn play(&mut self, state: &S, rng: &mut SmallRng) -> S;
+}
+
+/// Plays the best moves found by alpha-beta search, ties are broken at random.
+pub struct AlphaBeta<S> {
+    strategy: S,
+    depth: u8,
+}
+
+impl<S> AlphaBeta<S> {
+    pub fn new(strategy: S, depth: u8) -> Self {
+        Self { strategy, depth }
+    }
+}
+
+impl<S: min_max::Strategy> Engine<S::State> for AlphaBeta<S> where S::State: State {
+    fn play(&mut self, state: &S::State, rng: &mut SmallRng) -> S::State {
+        let player = state.player().into();
+        let moves = min_max::score_possible_moves_for(&mut self.strategy, state, self.depth, player);
+        let best = moves.iter().map(|m| m.score).max().expect("there is a move while the game is not over");
+        let m = &moves.iter().filter(|m| m.score == best).choose(rng).expect("the best move exists").min_max_move;
+        self.strategy.do_move(state, m, player)
+    }
+}
+
+/// Plays random moves.
+pub struct RandomMoves<S> {
+    strategy: S,
+}
+
+impl<S> RandomMoves<S> {
+    pub fn new(strategy: S) -> Self {
+        Self { strategy }
+    }
+}
+
+impl<S: min_max::Strategy> Engine<S::State> for RandomMoves<S> where S::State: State {
+    fn play(&mut self, state: &S::State, rng: &mut SmallRng) -> S::State {
+        let m = S::possible_moves(state).into_iter().choose(rng).expect("there is a move while the game is not over");
+        self.strategy.do_move(state, &m, state.player().into())
+    }
+}
+
+/// Plays the move Monte Carlo Tree Search visited most, ties are broken at random.
+pub struct Mcts<S> {
+    strategy: S,
+    config: mcts::Config,
+}
+
+impl<S> Mcts<S> {
+    pub fn new(strategy: S, config: mcts::Config) -> Self {
+        Self { strategy, config }
+    }
+}
+
+impl<S: min_max::Strategy> Engine<S::State> for Mcts<S> where S::State: State + Clone {
+    fn play(&mut self, state: &S::State, rng: &mut SmallRng) -> S::State {
+        let player = state.player().into();
+        let moves = mcts::search_for(&mut self.strategy, state, player, &self.config, rng);
+        let most = moves.iter().map(|m| m.visits).max().expect("there is a move while the game is not over");
+        let m = &moves.iter().filter(|m| m.visits == most).choose(rng).expect("the most visited move exists").min_max_move;
+        self.strategy.do_move(state, m, player)
+    }
+}
+
+/// Plays the moves with the best expected score, or random moves without a depth.
+pub struct ExpectiMinMax<S> {
+    strategy: S,
+    depth: Option<u8>,
+}
+
+impl<S> ExpectiMinMax<S> {
+    pub fn new(strategy: S, depth: u8) -> Self {
+        Self { strategy, depth: Some(depth) }
+    }
+
+    pub fn random(strategy: S) -> Self {
+        Self { strategy, depth: None }
+    }
+}
+
+impl<S: expecti_min_max::Strategy> Engine<S::State> for ExpectiMinMax<S> where S::State: State, S::Move: Clone {
+    fn play(&mut self, state: &S::State, rng: &mut SmallRng) -> S::State {
+        let player = state.player().into();
+        let m = match self.depth {
+            Some(depth) => {
+                let moves = expecti_min_max::score_possible_moves_for(&mut self.strategy, state, depth, player);
+                let best = moves.iter().map(|m| m.score).max().expect("there is a move while the game is not over");
+                moves.into_iter().filter(|m| m.score == best).choose(rng).expect("the best move exists").min_max_move
+            }
+            None => match S::possible_moves(state) {
+                Moves::Player(moves) => moves.into_iter().choose(rng).expect("there is a move while the game is not over"),
+                Moves::Chance(_) => panic!("Chance must be resolved before the engine moves"),
+            },
+        };
+        self.strategy.do_move(state, &m, player)
+    }
+}
+
+/// Resolves the chance in `state` at random, e.g. rolls the dice for `player`.
+pub fn settle<S: expecti_min_max::Strategy>(strategy: &mut S, state: S::State, player: Player, rng: &mut SmallRng) -> S::State {
+    let outcome = match S::possible_moves(&state) {
+        Moves::Chance(outcomes) => outcomes.into_iter().choose(rng),
+        Moves::Player(_) => None,
+    };
+    match outcome {
+        Some(outcome) => strategy.do_move(&state, &outcome, player),
+        None => state,
+    }
+}
+
+/// The result of a match between two engines, from the view of the first one.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MatchResult {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Summed over all moves of the first and of the second engine.
+    pub think_time: [Duration; 2],
+    pub moves: [u32; 2],
+}
+
+impl MatchResult {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The share of points of the first engine, a draw counts half.
+    pub fn score(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games())
+    }
+
+    /// The Elo difference of the first engine to the second one and the margin of its 95% confidence interval. The
+    /// score is kept half a game away from a clean sweep, as that would be an infinite difference.
+    pub fn elo(&self) -> (f64, f64) {
+        let games = f64::from(self.games());
+        let clamp = |score: f64| score.clamp(0.5 / games, 1.0 - 0.5 / games);
+        let score = clamp(self.score());
+        let variance = (f64::from(self.wins) * (1.0 - score).powi(2)
+            + f64::from(self.draws) * (0.5 - score).powi(2)
+            + f64::from(self.losses) * score.powi(2)) / games;
+        let margin = 1.96 * (variance / games).sqrt();
+        let low = elo_difference(clamp(score - margin));
+        let high = elo_difference(clamp(score + margin));
+        (elo_difference(score), (high - low) / 2.0)
+    }
+
+    pub fn average_think_time(&self, engine: usize) -> Duration {
+        self.think_time[engine].checked_div(self.moves[engine]).unwrap_or_default()
+    }
+}
+
+impl Display for MatchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (elo, margin) = self.elo();
+        write!(
+            f,
+            "+{} ={} -{}, Elo {:+.0} ± {:.0}, think time {:.1?} vs {:.1?}",
+            self.wins, self.draws, self.losses, elo, margin, self.average_think_time(0), self.average_think_time(1),
+        )
+    }
+}
+
+// the Elo difference at which the stronger player is expected to make `score` of the points
+fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Plays `games` games between the engines, which take turns in starting. Game `i` and `i + 1` are played with the
+/// same seed, so both engines get to play from the same random events. `settle` resolves the chance after every move
+/// for the given player to move next, e.g. with [settle], and is the identity for games without chance. Chance has its
+/// own rng, so the random choices of the engines don't change the events.
+pub fn play_match<S: State + Clone>(
+    initial: &dyn Fn(&mut SmallRng) -> S,
+    settle: &mut dyn FnMut(S, Player, &mut SmallRng) -> S,
+    engines: [&mut dyn Engine<S>; 2],
+    games: u32,
+    seed: u64,
+) -> MatchResult {
+    let mut result = MatchResult::default();
+    for game in 0..games {
+        let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(u64::from(game / 2)));
+        let mut chance = SmallRng::from_rng(&mut rng);
+        // the engine that plays the side starting the game
+        let starter = (game % 2) as usize;
+        let mut state = initial(&mut chance);
+        let first_side = state.player();
+        let engine_of = |side: Side| if side == first_side { starter } else { 1 - starter };
+        while state.status() == Status::Playing {
+            let side = state.player();
+            let engine = engine_of(side);
+            let start = Instant::now();
+            let next = engines[engine].play(&state, &mut rng);
+            result.think_time[engine] += start.elapsed();
+            result.moves[engine] += 1;
+            state = settle(next, !Player::from(side), &mut chance);
+        }
+        match state.status() {
+            Status::Playing => unreachable!(),
+            Status::Draw => result.draws += 1,
+            Status::Done { winner } if engine_of(winner) == 0 => result.wins += 1,
+            Status::Done { .. } => result.losses += 1,
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::common::BaseStrategy;
+    use crate::game_controller::Replay;
+    use crate::min_max::cache::NullCache;
+    use crate::{knucklebones, ttt};
+    use super::*;
+
+    #[test]
+    fn elo_of_results() {
+        let even = MatchResult { wins: 5, draws: 10, losses: 5, ..MatchResult::default() };
+        assert_eq!(even.elo().0, 0.0);
+        assert!(even.elo().1 > 0.0);
+
+        let stronger = MatchResult { wins: 15, draws: 0, losses: 5, ..MatchResult::default() };
+        let (elo, margin) = stronger.elo();
+        assert!((elo - 190.8).abs() < 0.1, "{}", elo);
+        assert!(margin > 100.0 && margin < elo * 2.0);
+
+        // a clean sweep counts as three and a half of four games
+        let perfect = MatchResult { wins: 4, ..MatchResult::default() };
+        let (elo, margin) = perfect.elo();
+        assert!((elo - 338.0).abs() < 0.1, "{}", elo);
+        assert!(margin.is_finite() && margin > 0.0, "{}", margin);
+        let swept = MatchResult { losses: 2, ..MatchResult::default() };
+        assert!((swept.elo().0 + 190.8).abs() < 0.1, "{}", swept.elo().0);
+        assert!(swept.elo().1.is_finite());
+    }
+
+    #[test]
+    fn perfect_ttt_never_loses() {
+        type Strategy = BaseStrategy<ttt::GameBoard, NullCache>;
+        let mut perfect = AlphaBeta::new(Strategy::new(NullCache), u8::MAX);
+        let mut random = RandomMoves::new(Strategy::new(NullCache));
+        let result = play_match(&|_| Strategy::initial(), &mut |state, _, _| state, [&mut perfect, &mut random], 10, 0);
+        assert_eq!(result.games(), 10);
+        assert_eq!(result.losses, 0);
+        assert!(result.wins > 0);
+        assert!(result.moves[0] > 0 && result.moves[1] > 0);
+    }
+
+    #[test]
+    fn mcts_beats_random_moves() {
+        type Strategy = BaseStrategy<ttt::GameBoard, NullCache>;
+        let config = mcts::Config { budget: mcts::Budget::Iterations(500), ..mcts::Config::default() };
+        let mut mcts = Mcts::new(Strategy::new(NullCache), config);
+        let mut random = RandomMoves::new(Strategy::new(NullCache));
+        let result = play_match(&|_| Strategy::initial(), &mut |state, _, _| state, [&mut mcts, &mut random], 10, 0);
+        assert_eq!(result.games(), 10);
+        assert!(result.wins > result.losses, "{}", result);
+    }
+
+    #[test]
+    fn seeded_knucklebones_matches_repeat() {
+        let initial = |rng: &mut SmallRng| settle(&mut knucklebones::Strategy::new(), knucklebones::State::empty(), Player::Min, rng);
+        let mut dice = knucklebones::Strategy::new();
+        let mut play = |seed| {
+            let mut greedy = ExpectiMinMax::new(knucklebones::Strategy::new(), 1);
+            let mut random = ExpectiMinMax::random(knucklebones::Strategy::new());
+            let mut settle_dice = |state, player, rng: &mut SmallRng| settle(&mut dice, state, player, rng);
+            play_match(&initial, &mut settle_dice, [&mut greedy, &mut random], 20, seed)
+        };
+        let result = play(7);
+        assert_eq!(result.games(), 20);
+        assert!(result.wins > result.losses);
+        let again = play(7);
+        assert_eq!((again.wins, again.draws, again.losses, again.moves), (result.wins, result.draws, result.losses, result.moves));
+    }
+}
@@ -0,0 +1,840 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufReader};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, SystemTime};
+use itertools::Itertools;
+use rand::prelude::*;
+use crate::{arena, connect_four, expecti_min_max, game_controller, knucklebones, mcts, min_max, protocol, stoplight, ttt, ultimate_ttt};
+use crate::arena::{play_match, AlphaBeta, Engine, ExpectiMinMax, Mcts, RandomMoves};
+use crate::expecti_min_max::Moves;
+use crate::common::BaseStrategy;
+use crate::fen::Fen;
+use crate::game_controller::{game_loop, replay_loop, Replay};
+use crate::min_max::{Algorithm, Player, SearchBudget, SearchConfig};
+use crate::min_max::cache::{HashMapCache, NullCache, ReplacementPolicy, SymmetricCache, TranspositionTable};
+use crate::min_max::deepening::{iterative_deepening_for, DeepeningResult};
+use crate::protocol::{play_chance_moves, ExpectiSession, MinMaxSession, Session};
+use crate::record::{self, Record};
+
+pub const USAGE: &str = "\
+Usage: rusty-solver <command> <game> [options]
+
+Commands:
+  play <game>                  play against the computer
+  replay <game> <file>         step through a saved game with next and back
+  analyze <game> <moves>...    score every move of the position reached by <moves>
+  solve <game>                 find the value and the best moves of the initial position
+  bench <game>                 measure the search speed on the initial position
+  arena <game> <engine>...     play matches between every pair of engines and estimate their Elo difference
+  engine <game>                speak a text protocol like UCI on stdin and stdout, starting with <game>
+
+Games: ttt, stoplight, ultimate-ttt, connect-four, knucklebones
+
+Moves are written as in play, e.g. 5 for ttt or 4,7 for ultimate-ttt. The players alternate such that the computer
+is to move after the last of them, unless they start from a position given by --fen. While playing, undo, redo,
+history, hint and save <file> can be entered instead of a move. Dice rolls of knucklebones are moves too, written as
+r1 to r6, and the die of the side to move has to be rolled before a search, e.g. analyze knucklebones r3 1 r6.
+
+Engines are alpha-beta, expecti (for knucklebones), mcts or random, optionally followed by settings that override the
+options, e.g. alpha-beta,depth=6,cache=none or expecti,depth=9. Alpha-beta engines of ultimate-ttt can score the
+positions by weighted features like threats and the centre instead of only the won boards with eval=weighted. Monte
+Carlo Tree Search runs 10000 playouts per move, or as many as iterations=<number> or time=<ms> allow.
+
+The engine reads the commands newgame <game>, position startpos|fen <position> [moves <move>...],
+go [depth <plies>] [movetime <ms>], stop, isready and quit.
+
+Options:
+  --depth <plies>     search depth, defaults to the whole game for ttt and stoplight
+  --time <ms>         time budget of analyze, solve and bench, the search deepens until it runs out
+  --cache <kind>      none, map, symmetric or table[:<megabytes>[:<policy>]], defaults to map and in play to the
+                      cache of the game. A symmetric map shares the entries of positions that are equal under
+                      symmetry. Tables keep the deeper entry with the depth policy (the default), the newer one
+                      with always or one of each with two-tier
+  --seed <number>     seed for the random choices of the computer in play
+  --load <file>       continue a saved game in play
+  --games <number>    games per match in the arena, defaults to 10
+  --fen <position>    start analyze, solve and bench from a position instead of the initial one, the cells row by
+                      row with digits for empty cells and the side to move, e.g. \"x2/1o1/3 x\" for ttt
+  --algorithm <name>  search of analyze, solve and bench, alpha-beta (the default), pvs for principal variation search
+                      or mtdf for MTD(f)
+";
+
+// size of the transposition table if `--cache table` doesn't give one
+const DEFAULT_TABLE_MEGABYTES: usize = 64;
+// cache of the searches if `--cache` isn't given, play keeps the one each game is tuned for instead
+const DEFAULT_CACHE: CacheKind = CacheKind::Map;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Game {
+    Ttt,
+    Stoplight,
+    UltimateTtt,
+    ConnectFour,
+    Knucklebones,
+}
+
+impl Game {
+    // deep enough to play well while the computer still answers within seconds
+    fn default_depth(self) -> u8 {
+        match self {
+            Game::Ttt | Game::Stoplight => u8::MAX,
+            Game::UltimateTtt => 8,
+            Game::ConnectFour => 10,
+            Game::Knucklebones => 15,
+        }
+    }
+
+    // the size of the board where there are others
+    fn variant(self) -> &'static str {
+        match self {
+            Game::Ttt => "3x3",
+            Game::ConnectFour => "7x6",
+            Game::Stoplight | Game::UltimateTtt | Game::Knucklebones => "standard",
+        }
+    }
+}
+
+impl Display for Game {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Game::Ttt => "ttt",
+            Game::Stoplight => "stoplight",
+            Game::UltimateTtt => "ultimate-ttt",
+            Game::ConnectFour => "connect-four",
+            Game::Knucklebones => "knucklebones",
+        })
+    }
+}
+
+impl FromStr for Game {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ttt" => Ok(Game::Ttt),
+            "stoplight" => Ok(Game::Stoplight),
+            "ultimate-ttt" => Ok(Game::UltimateTtt),
+            "connect-four" => Ok(Game::ConnectFour),
+            "knucklebones" => Ok(Game::Knucklebones),
+            _ => Err(format!("Unknown game {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CacheKind {
+    None,
+    Map,
+    /// A map shared by all positions that are equal under symmetry.
+    Symmetric,
+    Table { megabytes: usize, policy: ReplacementPolicy },
+}
+
+impl Display for CacheKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheKind::None => f.write_str("none"),
+            CacheKind::Map => f.write_str("map"),
+            CacheKind::Symmetric => f.write_str("symmetric"),
+            CacheKind::Table { megabytes, policy: ReplacementPolicy::DepthPreferred } => write!(f, "table:{}", megabytes),
+            CacheKind::Table { megabytes, policy } => write!(f, "table:{}:{}", megabytes, policy_name(*policy)),
+        }
+    }
+}
+
+impl FromStr for CacheKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        let table = |megabytes, policy| Ok(CacheKind::Table { megabytes, policy });
+        match parts.as_slice() {
+            ["none"] => Ok(CacheKind::None),
+            ["map"] => Ok(CacheKind::Map),
+            ["symmetric"] => Ok(CacheKind::Symmetric),
+            ["table"] => table(DEFAULT_TABLE_MEGABYTES, ReplacementPolicy::DepthPreferred),
+            ["table", megabytes] => table(parse_value("--cache", megabytes)?, ReplacementPolicy::DepthPreferred),
+            ["table", megabytes, policy] => table(parse_value("--cache", megabytes)?, parse_policy(policy)?),
+            _ => Err(format!("Unknown cache {}, valid caches are none, map, symmetric and table[:<megabytes>[:<policy>]]", s)),
+        }
+    }
+}
+
+fn parse_algorithm(s: &str) -> Result<Algorithm, String> {
+    match s {
+        "alpha-beta" => Ok(Algorithm::AlphaBeta),
+        "pvs" => Ok(Algorithm::PrincipalVariation),
+        "mtdf" => Ok(Algorithm::Mtdf),
+        _ => Err(format!("Unknown algorithm {}, valid algorithms are alpha-beta, pvs and mtdf", s)),
+    }
+}
+
+fn policy_name(policy: ReplacementPolicy) -> &'static str {
+    match policy {
+        ReplacementPolicy::DepthPreferred => "depth",
+        ReplacementPolicy::AlwaysReplace => "always",
+        ReplacementPolicy::TwoTier => "two-tier",
+    }
+}
+
+fn parse_policy(s: &str) -> Result<ReplacementPolicy, String> {
+    match s {
+        "depth" => Ok(ReplacementPolicy::DepthPreferred),
+        "always" => Ok(ReplacementPolicy::AlwaysReplace),
+        "two-tier" => Ok(ReplacementPolicy::TwoTier),
+        _ => Err(format!("Unknown replacement policy {}, valid policies are depth, always and two-tier", s)),
+    }
+}
+
+/// How the search scores positions it can't see to the end of, only ultimate-ttt has more than the default.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EvaluatorKind {
+    Default,
+    Weighted,
+}
+
+impl Display for EvaluatorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvaluatorKind::Default => f.write_str("default"),
+            EvaluatorKind::Weighted => f.write_str("weighted"),
+        }
+    }
+}
+
+impl FromStr for EvaluatorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(EvaluatorKind::Default),
+            "weighted" => Ok(EvaluatorKind::Weighted),
+            _ => Err(format!("Unknown evaluation {}, valid evaluations are default and weighted", s)),
+        }
+    }
+}
+
+/// The kind of engine, see [EngineConfig].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EngineKind {
+    AlphaBeta,
+    ExpectiMinMax,
+    Mcts,
+    Random,
+}
+
+/// How an engine of the arena is set up, written as the kind optionally followed by settings, e.g. `random` or
+/// `alpha-beta,depth=6,cache=table:16,eval=weighted` or `mcts,iterations=5000`. Settings left out are the defaults of
+/// the game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EngineConfig {
+    pub kind: EngineKind,
+    pub depth: Option<u8>,
+    pub cache: Option<CacheKind>,
+    pub eval: Option<EvaluatorKind>,
+    pub budget: Option<mcts::Budget>,
+}
+
+impl Display for EngineConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self.kind {
+            EngineKind::AlphaBeta => "alpha-beta",
+            EngineKind::ExpectiMinMax => "expecti",
+            EngineKind::Mcts => "mcts",
+            EngineKind::Random => "random",
+        })?;
+        if let Some(depth) = self.depth {
+            write!(f, ",depth={}", depth)?;
+        }
+        if let Some(cache) = &self.cache {
+            write!(f, ",cache={}", cache)?;
+        }
+        if let Some(eval) = &self.eval {
+            write!(f, ",eval={}", eval)?;
+        }
+        match self.budget {
+            Some(mcts::Budget::Iterations(iterations)) => write!(f, ",iterations={}", iterations)?,
+            Some(mcts::Budget::Time(time)) => write!(f, ",time={}", time.as_millis())?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EngineConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let kind = match parts.next() {
+            Some("alpha-beta") => EngineKind::AlphaBeta,
+            Some("expecti") => EngineKind::ExpectiMinMax,
+            Some("mcts") => EngineKind::Mcts,
+            Some("random") => EngineKind::Random,
+            _ => return Err(format!("Unknown engine {}, valid engines are alpha-beta, expecti, mcts and random", s)),
+        };
+        let mut config = EngineConfig { kind, depth: None, cache: None, eval: None, budget: None };
+        for setting in parts {
+            match setting.split_once('=') {
+                Some(("depth", depth)) => config.depth = Some(parse_depth(s, depth)?),
+                Some(("cache", cache)) => config.cache = Some(cache.parse()?),
+                Some(("eval", eval)) => config.eval = Some(eval.parse()?),
+                // without a single playout there is no move to choose from
+                Some(("iterations", iterations)) => match parse_value(s, iterations)? {
+                    0 => return Err(format!("{} has to run at least one iteration", s)),
+                    iterations => config.budget = Some(mcts::Budget::Iterations(iterations)),
+                },
+                Some(("time", time)) => match parse_value(s, time)? {
+                    0 => return Err(format!("{} has to run for at least 1ms", s)),
+                    time => config.budget = Some(mcts::Budget::Time(Duration::from_millis(time))),
+                },
+                _ => return Err(format!("Unknown setting {} of engine {}", setting, s)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Options shared by all commands, `None` means the default of the game.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Options {
+    pub depth: Option<u8>,
+    pub time: Option<Duration>,
+    pub cache: Option<CacheKind>,
+    pub seed: Option<u64>,
+    pub load: Option<String>,
+    pub games: u32,
+    pub fen: Option<String>,
+    pub algorithm: Algorithm,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { depth: None, time: None, cache: None, seed: None, load: None, games: 10, fen: None, algorithm: Algorithm::AlphaBeta }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    Play,
+    Replay { file: String },
+    Analyze { moves: Vec<String> },
+    Solve,
+    Bench,
+    Arena { engines: Vec<EngineConfig> },
+    Engine,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Invocation {
+    pub command: Command,
+    pub game: Game,
+    pub options: Options,
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value {} for {}", value, option))
+}
+
+// a search of depth 0 doesn't look at any move
+fn parse_depth(option: &str, value: &str) -> Result<u8, String> {
+    match parse_value(option, value)? {
+        0 => Err(format!("The depth of {} has to be at least 1", option)),
+        depth => Ok(depth),
+    }
+}
+
+/// Parses the arguments without the name of the program.
+pub fn parse_args<I: IntoIterator<Item=String>>(args: I) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+    let command = args.next().ok_or("Missing command")?;
+    let game = args.next().ok_or("Missing game")?.parse()?;
+    let mut options = Options::default();
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| args.next().ok_or_else(|| format!("Missing value for {}", option));
+        match arg.as_str() {
+            "--depth" => options.depth = Some(parse_depth(&arg, &value(&arg)?)?),
+            "--time" => options.time = Some(Duration::from_millis(parse_value(&arg, &value(&arg)?)?)),
+            "--cache" => options.cache = Some(value(&arg)?.parse()?),
+            "--seed" => options.seed = Some(parse_value(&arg, &value(&arg)?)?),
+            "--load" => options.load = Some(value(&arg)?),
+            "--games" => options.games = match parse_value(&arg, &value(&arg)?)? {
+                0 => return Err("The arena needs to play at least one game".to_string()),
+                games => games,
+            },
+            "--fen" => options.fen = Some(value(&arg)?),
+            "--algorithm" => options.algorithm = parse_algorithm(&value(&arg)?)?,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            // a quoted list of moves counts the same as separate arguments
+            _ => positional.extend(arg.split_whitespace().map(String::from)),
+        }
+    }
+    let command = match command.as_str() {
+        "play" => Command::Play,
+        "replay" if positional.is_empty() => return Err("Missing record file".to_string()),
+        "replay" => Command::Replay { file: positional.remove(0) },
+        "analyze" => Command::Analyze { moves: std::mem::take(&mut positional) },
+        "solve" => Command::Solve,
+        "bench" => Command::Bench,
+        "arena" if positional.len() < 2 => return Err("The arena needs at least two engines".to_string()),
+        "arena" => Command::Arena { engines: positional.drain(..).map(|engine| engine.parse()).collect::<Result<_, _>>()? },
+        "engine" => Command::Engine,
+        _ => return Err(format!("Unknown command {}", command)),
+    };
+    if let Some(unexpected) = positional.first() {
+        return Err(format!("Unexpected argument {}", unexpected));
+    }
+    Ok(Invocation { command, game, options })
+}
+
+// Binds `$strategy` to a new strategy using the selected cache. Every cache is a different type, so `$body` is
+// instantiated once per cache.
+macro_rules! with_cache {
+    ($cache:expr, $new:expr, |$strategy:pat_param| $body:expr) => {
+        match $cache {
+            CacheKind::None => {
+                let $strategy = $new(NullCache);
+                $body
+            }
+            CacheKind::Map => {
+                let $strategy = $new(HashMapCache::default());
+                $body
+            }
+            CacheKind::Symmetric => {
+                let $strategy = $new(SymmetricCache::new(HashMapCache::default()));
+                $body
+            }
+            CacheKind::Table { megabytes, policy } => {
+                let $strategy = $new(TranspositionTable::with_capacity_mb(megabytes, policy));
+                $body
+            }
+        }
+    };
+}
+
+// Binds `$controller` to the controller of `$game` with the selected cache, or the cache of the game without one.
+// Every game and cache has its own controller type, so `$body` is instantiated once per combination.
+macro_rules! with_controller {
+    ($game:expr, $seed:expr, $depth:expr, $cache:expr, |$controller:ident| $body:expr) => {{
+        let rng = SmallRng::seed_from_u64($seed);
+        match ($game, $cache) {
+            (Game::Ttt, None) => {
+                let mut $controller = ttt::TicTacToe::new(rng, $depth);
+                $body
+            }
+            (Game::Ttt, Some(cache)) => {
+                with_cache!(cache, |cache| ttt::TicTacToe::with_cache(rng, cache, $depth), |mut $controller| $body)
+            }
+            (Game::Stoplight, None) => {
+                let mut $controller = stoplight::Stoplight::new(rng, $depth);
+                $body
+            }
+            (Game::Stoplight, Some(cache)) => {
+                with_cache!(cache, |cache| stoplight::Stoplight::with_cache(rng, cache, $depth), |mut $controller| $body)
+            }
+            (Game::UltimateTtt, None) => {
+                let mut $controller = ultimate_ttt::UltimateTicTacToe::new(rng, $depth);
+                $body
+            }
+            (Game::UltimateTtt, Some(cache)) => {
+                with_cache!(cache, |cache| ultimate_ttt::UltimateTicTacToe::with_cache(rng, cache, $depth), |mut $controller| $body)
+            }
+            (Game::ConnectFour, None) => {
+                let mut $controller = connect_four::ConnectFour::new(rng, $depth);
+                $body
+            }
+            (Game::ConnectFour, Some(cache)) => {
+                with_cache!(cache, |cache| connect_four::ConnectFour::with_cache(rng, cache, $depth), |mut $controller| $body)
+            }
+            (Game::Knucklebones, None) => {
+                let mut $controller = knucklebones::Knucklebones::with_seed($seed, $depth);
+                $body
+            }
+            (Game::Knucklebones, Some(cache)) => Err(format!("--cache {} can't be used, {} has no transpositions to cache", cache, $game)),
+        }
+    }};
+}
+
+// Runs the arena for a game without chance, `$new` creates its strategy for a cache and `$weighted` the one of
+// engines with the weighted evaluation. Engines without their own settings use `$depth` and `$cache`.
+macro_rules! min_max_arena {
+    ($configs:expr, $new:expr, $weighted:expr, $depth:expr, $cache:expr, $games:expr, $seed:expr) => {{
+        let mut engines = vec![];
+        for config in $configs {
+            let depth = config.depth.unwrap_or($depth);
+            let engine: Box<dyn Engine<_>> = match config.kind {
+                EngineKind::AlphaBeta if config.eval == Some(EvaluatorKind::Weighted) => {
+                    with_cache!(config.cache.unwrap_or($cache), $weighted, |strategy| Box::new(AlphaBeta::new(strategy, depth)))
+                }
+                EngineKind::AlphaBeta => with_cache!(config.cache.unwrap_or($cache), $new, |strategy| Box::new(AlphaBeta::new(strategy, depth))),
+                EngineKind::Mcts => {
+                    let budget = config.budget.unwrap_or(mcts::Config::default().budget);
+                    Box::new(Mcts::new($new(NullCache), mcts::Config { budget, ..mcts::Config::default() }))
+                }
+                EngineKind::Random => Box::new(RandomMoves::new($new(NullCache))),
+                EngineKind::ExpectiMinMax => return Err(format!("{} needs a game with chance", config)),
+            };
+            engines.push((config, engine));
+        }
+        let initial = initial_of(&$new(NullCache));
+        round_robin(&mut engines, &|_| initial.clone(), &mut |state, _, _| state, $games, $seed);
+        Ok(())
+    }};
+}
+
+pub fn run(invocation: &Invocation) -> Result<(), String> {
+    let Invocation { command, game, options } = invocation;
+    let depth = options.depth.unwrap_or(game.default_depth());
+    match command {
+        Command::Play => {
+            let record = match &options.load {
+                Some(file) => load_record(*game, file)?,
+                None => new_record(*game),
+            };
+            let (record, seed) = seeded(record, options)?;
+            return with_controller!(*game, seed, depth, options.cache, |controller| game_loop(&mut controller, &record));
+        }
+        Command::Replay { file } => {
+            let (record, seed) = seeded(load_record(*game, file)?, options)?;
+            return with_controller!(*game, seed, depth, options.cache, |controller| replay_loop(&mut controller, &record));
+        }
+        Command::Arena { engines } => {
+            let seed = options.seed.unwrap_or_else(rand::random);
+            let weighted = engines.iter().find(|config| config.eval == Some(EvaluatorKind::Weighted));
+            if let Some(config) = weighted.filter(|_| *game != Game::UltimateTtt) {
+                return Err(format!("{} needs ultimate-ttt, {} only has the default evaluation", config, game));
+            }
+            println!("Seed: {}", seed);
+            return match game {
+                Game::Ttt => min_max_arena!(engines, BaseStrategy::<ttt::GameBoard, _>::new, BaseStrategy::<ttt::GameBoard, _>::new, depth, options.cache.unwrap_or(DEFAULT_CACHE), options.games, seed),
+                Game::Stoplight => min_max_arena!(engines, stoplight::Strategy::new, stoplight::Strategy::new, depth, options.cache.unwrap_or(DEFAULT_CACHE), options.games, seed),
+                Game::UltimateTtt => min_max_arena!(engines, ultimate_ttt::Strategy::new, ultimate_ttt::Strategy::weighted, depth, options.cache.unwrap_or(DEFAULT_CACHE), options.games, seed),
+                Game::ConnectFour => min_max_arena!(engines, connect_four::Strategy::new, connect_four::Strategy::new, depth, options.cache.unwrap_or(DEFAULT_CACHE), options.games, seed),
+                Game::Knucklebones => knucklebones_arena(engines, depth, options.games, seed),
+            };
+        }
+        Command::Engine => {
+            let session = new_session(*game, options)?;
+            protocol::run(BufReader::new(io::stdin()), io::stdout(), Some(session), |name| new_session(name.parse()?, options));
+            return Ok(());
+        }
+        _ => {}
+    }
+    match game {
+        Game::Ttt => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), BaseStrategy::<ttt::GameBoard, _>::new, |mut strategy| search(&mut strategy, command, depth, options)),
+        Game::Stoplight => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), stoplight::Strategy::new, |mut strategy| search(&mut strategy, command, depth, options)),
+        Game::UltimateTtt => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), ultimate_ttt::Strategy::new, |mut strategy| search(&mut strategy, command, depth, options)),
+        Game::ConnectFour => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), connect_four::Strategy::new, |mut strategy| search(&mut strategy, command, depth, options)),
+        Game::Knucklebones => expecti_search(&mut knucklebones::Strategy::new(), knucklebones::State::empty(), command, depth, options),
+    }
+}
+
+// Every pair of engines plays a match, the first engine of the pair is the one the result is for.
+fn round_robin<S: game_controller::State + Clone>(
+    engines: &mut [(&EngineConfig, Box<dyn Engine<S>>)],
+    initial: &dyn Fn(&mut SmallRng) -> S,
+    settle: &mut dyn FnMut(S, min_max::Player, &mut SmallRng) -> S,
+    games: u32,
+    seed: u64,
+) {
+    for first in 0..engines.len() {
+        for second in first + 1..engines.len() {
+            let (left, right) = engines.split_at_mut(second);
+            let (first_config, first_engine) = &mut left[first];
+            let (second_config, second_engine) = &mut right[0];
+            let result = play_match(initial, settle, [first_engine.as_mut(), second_engine.as_mut()], games, seed);
+            println!("{} vs {}: {}", first_config, second_config, result);
+        }
+    }
+}
+
+fn knucklebones_arena(configs: &[EngineConfig], depth: u8, games: u32, seed: u64) -> Result<(), String> {
+    let mut engines = vec![];
+    for config in configs {
+        let strategy = knucklebones::Strategy::new();
+        let engine: Box<dyn Engine<_>> = match (config.kind, config.cache) {
+            (_, Some(_)) => return Err(format!("{} can't use a cache in knucklebones", config)),
+            (EngineKind::ExpectiMinMax, None) => Box::new(ExpectiMinMax::new(strategy, config.depth.unwrap_or(depth))),
+            (EngineKind::Random, None) => Box::new(ExpectiMinMax::random(strategy)),
+            (EngineKind::AlphaBeta | EngineKind::Mcts, None) => return Err(format!("{} can't play a game with chance", config)),
+        };
+        engines.push((config, engine));
+    }
+    let mut dice = knucklebones::Strategy::new();
+    let initial = |rng: &mut SmallRng| arena::settle(&mut knucklebones::Strategy::new(), knucklebones::State::empty(), min_max::Player::Min, rng);
+    round_robin(&mut engines, &initial, &mut |state, player, rng| arena::settle(&mut dice, state, player, rng), games, seed);
+    Ok(())
+}
+
+fn new_session(game: Game, options: &Options) -> Result<Box<dyn Session>, String> {
+    match game {
+        Game::Ttt => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), BaseStrategy::<ttt::GameBoard, _>::new, |strategy| Ok(Box::new(MinMaxSession::new(strategy)) as Box<dyn Session>)),
+        Game::Stoplight => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), stoplight::Strategy::new, |strategy| Ok(Box::new(MinMaxSession::new(strategy)) as Box<dyn Session>)),
+        Game::UltimateTtt => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), ultimate_ttt::Strategy::new, |strategy| Ok(Box::new(MinMaxSession::new(strategy)) as Box<dyn Session>)),
+        Game::ConnectFour => with_cache!(options.cache.unwrap_or(DEFAULT_CACHE), connect_four::Strategy::new, |strategy| Ok(Box::new(MinMaxSession::new(strategy)) as Box<dyn Session>)),
+        Game::Knucklebones => Ok(Box::new(ExpectiSession::new(knucklebones::Strategy::new(), knucklebones::State::empty()))),
+    }
+}
+
+fn initial_of<S: Replay>(_strategy: &S) -> S::State where <S::Notation as FromStr>::Err: Display {
+    S::initial()
+}
+
+fn new_record(game: Game) -> Record {
+    let mut record = Record::new();
+    record.set_tag("Game", game.to_string());
+    record.set_tag("Variant", game.variant());
+    record.set_tag("Date", record::date(SystemTime::now()));
+    record
+}
+
+// --seed takes precedence over the seed of the record, a new seed is drawn if neither gives one
+fn seeded(mut record: Record, options: &Options) -> Result<(Record, u64), String> {
+    let seed = match (options.seed, record.tag("Seed")) {
+        (Some(seed), _) => seed,
+        (None, Some(tag)) => parse_value("the Seed tag", tag)?,
+        (None, None) => rand::random(),
+    };
+    record.set_tag("Seed", seed.to_string());
+    Ok((record, seed))
+}
+
+fn load_record(game: Game, file: &str) -> Result<Record, String> {
+    let record = Record::load(file)?;
+    match record.tag("Game") {
+        Some(tag) if tag == game.to_string() => Ok(record),
+        Some(tag) => Err(format!("{} is a record of {}, not {}", file, tag, game)),
+        None => Err(format!("{} has no Game tag", file)),
+    }
+}
+
+/// Plays `moves` from the initial position. The players alternate such that [Player::Max], the player the search
+/// moves for, is to move after the last of them.
+pub fn replay<S: Replay>(strategy: &mut S, moves: &[String]) -> Result<S::State, String> where <S::Notation as FromStr>::Err: Display {
+    let player = if moves.len().is_multiple_of(2) { Player::Max } else { Player::Min };
+    play_moves(strategy, S::initial(), player, moves).map(|(state, _)| state)
+}
+
+/// Plays `moves` from `state`, in which `player` is to move, and returns the position and the player to move after
+/// them.
+pub fn play_moves<S: Replay>(strategy: &mut S, mut state: S::State, mut player: Player, moves: &[String]) -> Result<(S::State, Player), String>
+where <S::Notation as FromStr>::Err: Display {
+    for m in moves {
+        let notation = m.parse::<S::Notation>().map_err(|e| e.to_string())?;
+        state = strategy.play(&state, notation, player)?;
+        player = !player;
+    }
+    Ok((state, player))
+}
+
+fn search<S: Replay>(strategy: &mut S, command: &Command, depth: u8, options: &Options) -> Result<(), String>
+    where S::Move: Clone, S::State: game_controller::State + Clone, <S::Notation as FromStr>::Err: Display, Fen<S::State>: FromStr<Err=String> + Display {
+    let moves = match command {
+        Command::Analyze { moves } => moves.as_slice(),
+        _ => &[],
+    };
+    let (state, player) = match &options.fen {
+        Some(fen) => {
+            let Fen(state) = fen.parse::<Fen<S::State>>()?;
+            let player = game_controller::State::player(&state).into();
+            play_moves(strategy, state, player, moves)?
+        }
+        None => (replay(strategy, moves)?, Player::Max),
+    };
+    if let Command::Analyze { .. } = command {
+        println!("Position: {}", Fen(state.clone()));
+    }
+    let budget = SearchBudget { time: options.time, nodes: None };
+    let result = iterative_deepening_for(strategy, &state, player, budget, depth, SearchConfig::new(options.algorithm))
+        .ok_or("Not even a search of depth 1 finished within the time budget")?;
+    print_result(command, &result, |m| S::notations(&state, m).iter().join(" "));
+    Ok(())
+}
+
+// Like `search` for the games with chance, which is played out by the moves up to the side to move.
+fn expecti_search<S: expecti_min_max::Strategy>(strategy: &mut S, initial: S::State, command: &Command, depth: u8, options: &Options) -> Result<(), String>
+    where S::Move: Clone + Display + FromStr, <S::Move as FromStr>::Err: Display, S::State: game_controller::State + Clone, Fen<S::State>: FromStr<Err=String> + Display {
+    let moves = match command {
+        Command::Analyze { moves } => moves.as_slice(),
+        _ => &[],
+    };
+    let state = match &options.fen {
+        Some(fen) => fen.parse::<Fen<S::State>>()?.0,
+        None => initial,
+    };
+    let state = play_chance_moves(strategy, state, moves)?;
+    if let Command::Analyze { .. } = command {
+        println!("Position: {}", Fen(state.clone()));
+    }
+    if let Moves::Chance(outcomes) = S::possible_moves(&state) {
+        return match outcomes.is_empty() {
+            true => Err("The game is already over".to_string()),
+            false => Err("Chance has to be played out before the search, e.g. the dice rolled".to_string()),
+        };
+    }
+    let player = game_controller::State::player(&state).into();
+    let budget = SearchBudget { time: options.time, nodes: None };
+    let result = expecti_min_max::iterative_deepening_with(strategy, &state, player, budget, depth, Arc::new(AtomicBool::new(false)), |_| {})
+        .ok_or("Not even a search of depth 1 finished within the time budget")?;
+    print_result(command, &result, |m| m.to_string());
+    Ok(())
+}
+
+fn print_result<M>(command: &Command, result: &DeepeningResult<M>, notations: impl Fn(&M) -> String) {
+    match command {
+        Command::Play | Command::Replay { .. } | Command::Arena { .. } | Command::Engine => unreachable!("play, replay, arena and engine are handled before the search"),
+        Command::Analyze { .. } => {
+            for scored_move in &result.scored_moves {
+                println!("{:>6}  {}", scored_move.score, notations(&scored_move.min_max_move));
+            }
+        }
+        Command::Solve => match result.scored_moves.first() {
+            Some(best) => {
+                let best_moves = result.scored_moves.iter().take_while(|m| m.score == best.score);
+                println!("Value: {}", best.score);
+                println!("Best moves: {}", best_moves.map(|m| notations(&m.min_max_move)).join(" "));
+            }
+            None => println!("The game is already over"),
+        },
+        Command::Bench => {
+            let seconds = result.elapsed.as_secs_f64();
+            println!("Nodes per second: {:.0}", result.nodes as f64 / seconds);
+        }
+    }
+    print_summary(result);
+}
+
+fn print_summary<M>(result: &DeepeningResult<M>) {
+    println!("Depth: {}, nodes: {}, time: {}ms", result.depth, result.nodes, result.elapsed.as_millis());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let invocation = parse_args(args("analyze ultimate-ttt 5,5 5,1 --depth 6 --cache table:16 --time 500")).unwrap();
+        assert_eq!(invocation.command, Command::Analyze { moves: vec!["5,5".to_string(), "5,1".to_string()] });
+        assert_eq!(invocation.game, Game::UltimateTtt);
+        assert_eq!(invocation.options, Options {
+            depth: Some(6),
+            time: Some(Duration::from_millis(500)),
+            cache: Some(CacheKind::Table { megabytes: 16, policy: ReplacementPolicy::DepthPreferred }),
+            seed: None,
+            load: None,
+            games: 10,
+            fen: None,
+            algorithm: Algorithm::AlphaBeta,
+        });
+
+        let invocation = parse_args(args("play connect-four --seed 7")).unwrap();
+        assert_eq!(invocation.command, Command::Play);
+        assert_eq!(invocation.options, Options { seed: Some(7), ..Options::default() });
+
+        let invocation = parse_args(args("replay ttt game.txt --depth 3")).unwrap();
+        assert_eq!(invocation.command, Command::Replay { file: "game.txt".to_string() });
+        assert_eq!(invocation.options.depth, Some(3));
+        let invocation = parse_args(args("arena knucklebones expecti,depth=9 random --games 4")).unwrap();
+        assert_eq!(invocation.command, Command::Arena { engines: vec!["expecti,depth=9".parse().unwrap(), "random".parse().unwrap()] });
+        assert_eq!(invocation.options.games, 4);
+        let invocation = parse_args(args("play ttt --load game.txt")).unwrap();
+        assert_eq!(invocation.options.load, Some("game.txt".to_string()));
+        let invocation = parse_args(args("engine connect-four --cache none")).unwrap();
+        assert_eq!(invocation.command, Command::Engine);
+        assert_eq!(invocation.options.cache, Some(CacheKind::None));
+        let invocation = parse_args(["analyze", "ttt", "9", "--fen", "xx1/oo1/3 o"].map(String::from)).unwrap();
+        assert_eq!(invocation.command, Command::Analyze { moves: vec!["9".to_string()] });
+        assert_eq!(invocation.options.fen, Some("xx1/oo1/3 o".to_string()));
+        let invocation = parse_args(args("solve connect-four --algorithm mtdf")).unwrap();
+        assert_eq!(invocation.options.algorithm, Algorithm::Mtdf);
+    }
+
+    #[test]
+    fn parse_engine_config() {
+        let config = "alpha-beta,depth=6,cache=table:16".parse::<EngineConfig>().unwrap();
+        assert_eq!(config, EngineConfig { kind: EngineKind::AlphaBeta, depth: Some(6), cache: Some(CacheKind::Table { megabytes: 16, policy: ReplacementPolicy::DepthPreferred }), eval: None, budget: None });
+        assert_eq!(config.to_string(), "alpha-beta,depth=6,cache=table:16");
+        let config = "alpha-beta,eval=weighted".parse::<EngineConfig>().unwrap();
+        assert_eq!(config.eval, Some(EvaluatorKind::Weighted));
+        assert_eq!(config.to_string(), "alpha-beta,eval=weighted");
+        assert_eq!("random".parse::<EngineConfig>().unwrap().to_string(), "random");
+        let config = "mcts,iterations=5000".parse::<EngineConfig>().unwrap();
+        assert_eq!(config.budget, Some(mcts::Budget::Iterations(5000)));
+        assert_eq!(config.to_string(), "mcts,iterations=5000");
+        assert_eq!("mcts,time=100".parse::<EngineConfig>().unwrap().budget, Some(mcts::Budget::Time(Duration::from_millis(100))));
+        assert!("mcts,iterations=0".parse::<EngineConfig>().is_err());
+        assert!("mcts,time=soon".parse::<EngineConfig>().is_err());
+        assert!("minimax".parse::<EngineConfig>().is_err());
+        assert!("expecti,depth=deep".parse::<EngineConfig>().is_err());
+        assert!("expecti,depth=0".parse::<EngineConfig>().is_err());
+        assert!("expecti,width=3".parse::<EngineConfig>().is_err());
+        assert!("alpha-beta,cache=disk".parse::<EngineConfig>().is_err());
+        let config = "alpha-beta,cache=table:8:two-tier".parse::<EngineConfig>().unwrap();
+        assert_eq!(config.cache, Some(CacheKind::Table { megabytes: 8, policy: ReplacementPolicy::TwoTier }));
+        assert_eq!(config.to_string(), "alpha-beta,cache=table:8:two-tier");
+        assert!("alpha-beta,cache=table:8:forever".parse::<EngineConfig>().is_err());
+        assert_eq!("alpha-beta,cache=symmetric".parse::<EngineConfig>().unwrap().cache, Some(CacheKind::Symmetric));
+        assert!("alpha-beta,eval=neural".parse::<EngineConfig>().is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse_args(args("play chess")).is_err());
+        assert!(parse_args(args("replay ttt")).is_err());
+        assert!(parse_args(args("replay ttt a.txt b.txt")).is_err());
+        assert!(parse_args(args("arena ttt random")).is_err());
+        assert!(parse_args(args("arena ttt random random --games 0")).is_err());
+        assert!(parse_args(args("solve ttt 5")).is_err());
+        assert!(parse_args(args("solve ttt --depth")).is_err());
+        assert!(parse_args(args("solve ttt --depth deep")).is_err());
+        assert!(parse_args(args("play ttt --depth 0")).is_err());
+        assert!(parse_args(args("solve ttt --cache disk")).is_err());
+        assert!(parse_args(args("solve ttt --algorithm negascout")).is_err());
+        assert!(parse_args(args("solve ttt --verbose")).is_err());
+    }
+
+    #[test]
+    fn searches_knucklebones_once_the_die_is_rolled() {
+        assert_eq!(run(&parse_args(args("analyze knucklebones r3 1 r6 --depth 2")).unwrap()), Ok(()));
+        assert_eq!(run(&parse_args(["solve", "knucklebones", "--fen", "-/-/- 6/-/- x 6", "--depth", "2"].map(String::from)).unwrap()), Ok(()));
+        assert!(run(&parse_args(args("solve knucklebones")).unwrap()).is_err());
+        assert!(run(&parse_args(args("analyze knucklebones r3 1")).unwrap()).is_err());
+    }
+
+    #[test]
+    fn play_rejects_a_cache_for_knucklebones() {
+        let invocation = parse_args(args("play knucklebones --cache map")).unwrap();
+        assert_eq!(run(&invocation), Err("--cache map can't be used, knucklebones has no transpositions to cache".to_string()));
+    }
+
+    #[test]
+    fn replay_leaves_max_to_move() {
+        use crate::ttt::CellState::{EMPTY, O, X};
+        let mut strategy = BaseStrategy::<ttt::GameBoard, _>::new(NullCache);
+        let state = replay(&mut strategy, &args("5 1 9")).unwrap();
+        assert_eq!(state.cells(), [X, EMPTY, EMPTY, EMPTY, O, EMPTY, EMPTY, EMPTY, O]);
+        assert_eq!(state.last_player, Player::Min);
+
+        let state = replay(&mut strategy, &args("5 1")).unwrap();
+        assert_eq!(state.cells(), [O, EMPTY, EMPTY, EMPTY, X, EMPTY, EMPTY, EMPTY, EMPTY]);
+        assert!(replay(&mut strategy, &args("5 5")).is_err());
+        assert!(replay(&mut strategy, &args("0")).is_err());
+    }
+
+    #[test]
+    fn moves_follow_position() {
+        let mut strategy = BaseStrategy::<ttt::GameBoard, _>::new(NullCache);
+        let Fen(state) = "xx1/oo1/3 o".parse::<Fen<ttt::GameBoard>>().unwrap();
+        let (state, player) = play_moves(&mut strategy, state, Player::Min, &args("9 3")).unwrap();
+        assert_eq!(Fen(state).to_string(), "xxx/oo1/2o o");
+        assert_eq!(player, Player::Min);
+    }
+}
@@ -1,9 +1,14 @@
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
-use lazy_static::lazy_static;
+use std::str::FromStr;
 use crate::min_max::{Player};
 use crate::min_max::cache::Cache;
 use crate::min_max::stats::NullStats;
-use crate::min_max::symmetry::{GridSymmetry3x3};
+use crate::min_max::zobrist;
+use crate::min_max::zobrist::ZobristHash;
+use crate::{fen, game_controller};
+use crate::fen::Fen;
+use crate::min_max::symmetry::{CanonicalState, GridSymmetry, GridSymmetryAxisContext, GridSymmetryAxisContextMxN};
 
 pub trait BoardStatus {
     fn is_max_won(&self) -> bool;
@@ -12,6 +17,11 @@ pub trait BoardStatus {
 
 pub trait Cell: Copy + Eq + Hash {
     fn empty() -> Self;
+    /// Distinct small number for every value of the cell, used for Zobrist hashing.
+    fn ordinal(&self) -> usize;
+    /// The letter of the cell in a [Fen], `None` for the empty cell.
+    fn symbol(&self) -> Option<char>;
+    fn from_symbol(symbol: char) -> Option<Self>;
 }
 
 pub trait Board: Clone + Eq + Hash {
@@ -21,58 +31,245 @@ pub trait Board: Clone + Eq + Hash {
     fn status(&self) -> Self::BoardStatus;
 }
 
+/// A board with `M` columns and `N` rows on which `K` equal cells in a row, column or diagonal win.
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-pub struct Board3x3<C: Cell> {
-    pub cells: [C; 9],
+pub struct BoardMxN<C: Cell, const M: usize, const N: usize, const K: usize> {
+    // use [BoardMxN::set_cell] to modify, the Zobrist hash is kept up to date there
+    rows: [[C; M]; N],
     pub last_player: Player,
-    //pub last_move: Option<u8>,
+    // Zobrist hash of the cells
+    hash: u64,
+    // index of the first line of `K` equal, non empty cells, also kept up to date by [BoardMxN::set_cell]
+    winning_line: Option<usize>,
 }
 
-impl<C: Cell> Board3x3<C> {
+pub type Board3x3<C> = BoardMxN<C, 3, 3, 3>;
+
+impl<C: Cell, const M: usize, const N: usize, const K: usize> BoardMxN<C, M, N, K> {
+    pub const CELLS: usize = M * N;
+    // Zobrist hashing has keys for that many cells and the move generation of ttt tracks the cells in an u128
+    const FITS: () = assert!(M * N <= zobrist::COMPONENTS && M * N <= u128::BITS as usize, "a board has at most 128 cells");
+
     pub fn empty() -> Self {
-        Self::new([C::empty(); 9], Player::Max)
+        Self::new(&vec![C::empty(); Self::CELLS], Player::Max)
     }
 
-    pub fn new(cells: [C; 9], last_player: Player) -> Self {
-        Self { cells, last_player }
+    /// `cells` are given row by row from the top left.
+    pub fn new(cells: &[C], last_player: Player) -> Self {
+        let () = Self::FITS;
+        assert_eq!(cells.len(), Self::CELLS, "a {}x{} board has {} cells", M, N, Self::CELLS);
+        let mut rows = [[C::empty(); M]; N];
+        rows.as_flattened_mut().copy_from_slice(cells);
+        let mut board = Self { rows, last_player, hash: Self::hash_cells(cells), winning_line: None };
+        board.winning_line = board.first_winning_line();
+        board
     }
-    
-    pub fn symmetry(&self) -> GridSymmetry3x3 {
-        GridSymmetry3x3::from(&self.cells)
+
+    fn hash_cells(cells: &[C]) -> u64 {
+        cells.iter().enumerate().fold(0, |hash, (index, cell)| hash ^ zobrist::key(index, cell.ordinal()))
+    }
+
+    /// All cells row by row from the top left.
+    pub fn cells(&self) -> &[C] {
+        self.rows.as_flattened()
+    }
+
+    pub fn set_cell(&mut self, index: usize, cell: C) {
+        let current = &mut self.rows.as_flattened_mut()[index];
+        self.hash ^= zobrist::key(index, current.ordinal()) ^ zobrist::key(index, cell.ordinal());
+        *current = cell;
+        // only the lines through the cell can have been completed or broken
+        self.winning_line = match self.winning_line {
+            Some(line) if Self::line_indices(line).any(|line_index| line_index == index) => self.first_winning_line(),
+            winning_line => winning_line.into_iter().chain(self.winning_line_through(index)).min(),
+        };
+    }
+
+    pub fn symmetry(&self) -> GridSymmetry<GridSymmetryAxisContextMxN<M, N>> {
+        GridSymmetry::of_cells(self.cells())
+    }
+
+    /// The cell indices of the first line of `K` equal, non empty cells. It is tracked while cells are set, so this
+    /// doesn't scan the board.
+    pub fn winning_indices(&self) -> Option<impl Iterator<Item=usize>> {
+        self.winning_line.map(Self::line_indices)
+    }
+
+    /// The cell that fills the winning line, see [BoardMxN::winning_indices].
+    pub fn winner(&self) -> Option<C> {
+        self.winning_line.map(|line| self.cells()[Self::line_start_and_step(line).0])
+    }
+
+    // The lines are numbered rows first, then columns, then diagonals from the top left and then diagonals from the
+    // top right. These are the number of lines of each kind.
+    const LINE_COUNTS: [usize; 4] = {
+        let row_lines = if K <= M { N * (M - K + 1) } else { 0 };
+        let column_lines = if K <= N { M * (N - K + 1) } else { 0 };
+        let diagonal_lines = if K <= M && K <= N { (N - K + 1) * (M - K + 1) } else { 0 };
+        [row_lines, column_lines, diagonal_lines, diagonal_lines]
+    };
+    const LINES: usize = Self::LINE_COUNTS[0] + Self::LINE_COUNTS[1] + Self::LINE_COUNTS[2] + Self::LINE_COUNTS[3];
+    // the rows and columns a line of `K` cells can start at, 0 if it doesn't fit
+    const LINE_START_ROWS: usize = (N + 1).saturating_sub(K);
+    const LINE_START_COLUMNS: usize = (M + 1).saturating_sub(K);
+
+    // the first cell of the line and the distance of its cells
+    fn line_start_and_step(line: usize) -> (usize, usize) {
+        let [row_lines, column_lines, diagonal_lines, _] = Self::LINE_COUNTS;
+        let (start_rows, start_columns) = (Self::LINE_START_ROWS, Self::LINE_START_COLUMNS);
+        if line < row_lines {
+            (line / start_columns * M + line % start_columns, 1)
+        } else if line < row_lines + column_lines {
+            let line = line - row_lines;
+            (line % start_rows * M + line / start_rows, M)
+        } else if line < row_lines + column_lines + diagonal_lines {
+            let line = line - row_lines - column_lines;
+            (line / start_columns * M + line % start_columns, M + 1)
+        } else {
+            let line = line - row_lines - column_lines - diagonal_lines;
+            (line / start_columns * M + line % start_columns + K - 1, M - 1)
+        }
+    }
+
+    fn line_indices(line: usize) -> impl Iterator<Item=usize> {
+        let (start, step) = Self::line_start_and_step(line);
+        (0..K).map(move |i| start + i * step)
     }
 
-    pub fn winning_indices(&self) -> Option<&[usize; 3]> {
-        Self::WIN_INDICES.iter().find(|indices| {
-            self.cells[indices[0]] != C::empty() && self.cells[indices[0]] == self.cells[indices[1]] && self.cells[indices[1]] == self.cells[indices[2]]
+    // the lines through the cell at `index`, in no particular order
+    fn lines_through(index: usize) -> impl Iterator<Item=usize> {
+        let [row_lines, column_lines, diagonal_lines, _] = Self::LINE_COUNTS;
+        let (start_rows, start_columns) = (Self::LINE_START_ROWS, Self::LINE_START_COLUMNS);
+        let (row, column) = (index / M, index % M);
+        // `offset` is the distance of the cell from the first cell of the line
+        (0..K).flat_map(move |offset| {
+            let start_row = row.checked_sub(offset).filter(|start_row| *start_row < start_rows);
+            let start_column = column.checked_sub(offset).filter(|start_column| *start_column < start_columns);
+            // diagonals from the top right start `K - 1` columns further right than they end
+            let anti_start_column = (column + offset).checked_sub(K - 1).filter(|start_column| *start_column < start_columns);
+            [
+                start_column.map(|start_column| row * start_columns + start_column),
+                start_row.map(|start_row| row_lines + column * start_rows + start_row),
+                start_row.zip(start_column)
+                    .map(|(start_row, start_column)| row_lines + column_lines + start_row * start_columns + start_column),
+                start_row.zip(anti_start_column)
+                    .map(|(start_row, start_column)| row_lines + column_lines + diagonal_lines + start_row * start_columns + start_column),
+            ].into_iter().flatten()
         })
     }
 
-    const WIN_INDICES: [[usize; 3]; 8] = [
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8],
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8],
-        [0, 4, 8],
-        [2, 4, 6],
-    ];
-}
-
-lazy_static! {
-    static ref WIN_INDICES: [Vec<[usize; 3]>; 9] = [
-        vec![[0, 1, 2], [0, 3, 6],[0, 4, 8]],
-        vec![[0, 1, 2], [1, 4, 7]],
-        vec![[0, 1, 2], [2, 5, 8],[2, 4, 6]],
-        vec![[3, 4, 5], [0, 3, 6]],
-        vec![[3, 4, 5], [1, 4, 7],[0, 4, 8],[2, 4, 6]],
-        vec![[3, 4, 5], [2, 5, 8]],
-        vec![[6, 7, 8], [0, 3, 6],[2, 4, 6]],
-        vec![[6, 7, 8], [1, 4, 7]],
-        vec![[6, 7, 8], [2, 5, 8],[0, 4, 8]],
-    ];
+    fn first_winning_line(&self) -> Option<usize> {
+        (0..Self::LINES).find(|line| self.is_won(*line))
+    }
+
+    fn winning_line_through(&self, index: usize) -> Option<usize> {
+        Self::lines_through(index).filter(|line| self.is_won(*line)).min()
+    }
+
+    fn is_won(&self, line: usize) -> bool {
+        let cells = self.cells();
+        let mut line = Self::line_indices(line);
+        let first = cells[line.next().expect("a line has K > 0 cells")];
+        first != C::empty() && line.all(|index| cells[index] == first)
+    }
+}
+
+impl<C: Cell, const M: usize, const N: usize, const K: usize> CanonicalState for BoardMxN<C, M, N, K> {
+    /// The image of the board under the symmetry that yields the smallest cells, ordered by [Cell::ordinal].
+    fn canonical_state(&self) -> Self {
+        let ordinals = |cells: &[C]| cells.iter().map(|cell| cell.ordinal()).collect::<Vec<_>>();
+        let mut canonical = self.cells().to_vec();
+        let mut canonical_ordinals = ordinals(&canonical);
+        for axis in GridSymmetryAxisContextMxN::<M, N>::axes() {
+            let mut image = canonical.clone();
+            for (index, cell) in self.cells().iter().enumerate() {
+                image[GridSymmetryAxisContextMxN::<M, N>::transform(axis, index)] = *cell;
+            }
+            let image_ordinals = ordinals(&image);
+            if image_ordinals < canonical_ordinals {
+                canonical = image;
+                canonical_ordinals = image_ordinals;
+            }
+        }
+        if canonical == self.cells() {
+            *self
+        } else {
+            Self::new(&canonical, self.last_player)
+        }
+    }
+}
+
+impl<C: Cell, const M: usize, const N: usize, const K: usize> ZobristHash for BoardMxN<C, M, N, K> {
+    fn zobrist_hash(&self) -> u64 {
+        self.hash ^ zobrist::player_key(self.last_player)
+    }
+}
+
+impl<C: Cell + Display, const M: usize, const N: usize, const K: usize> Display for BoardMxN<C, M, N, K> {
+    /// Empty cells show their number counted from 1, which is how the human player enters moves.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = Self::CELLS.to_string().len();
+        for (row, cells) in self.rows.iter().enumerate() {
+            write!(f, "|")?;
+            for (column, cell) in cells.iter().enumerate() {
+                if *cell == C::empty() {
+                    write!(f, "{:>width$}|", row * M + column + 1)?;
+                } else {
+                    write!(f, "{:>width$}|", cell)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: Cell, const M: usize, const N: usize, const K: usize> Display for Fen<BoardMxN<C, M, N, K>> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fen::write_rows(f, self.0.rows.iter().map(|row| row.iter().map(Cell::symbol)))?;
+        write!(f, " {}", fen::side_symbol(!self.0.last_player))
+    }
+}
+
+impl<C: Cell, const M: usize, const N: usize, const K: usize> FromStr for Fen<BoardMxN<C, M, N, K>> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [rows, side] = fen::fields(s)?;
+        let cells = fen::parse_rows(rows, M, N)?.into_iter().flatten()
+            .map(|cell| match cell {
+                None => Ok(C::empty()),
+                Some(symbol) => C::from_symbol(symbol).ok_or_else(|| format!("Invalid cell {}", symbol)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Fen(BoardMxN::new(&cells, !fen::parse_side(side)?)))
+    }
+}
+
+/// The index of a cell of a grid entered by the human player, counted from 1 row by row from the top left. The notation
+/// fits grids of any size, whether the cell exists is checked by the board it is played on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CellIndex(pub usize);
+
+impl Display for CellIndex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+impl FromStr for CellIndex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(index @ 1..) => Ok(CellIndex(index - 1)),
+            _ => Err(format!("Invalid move {}, cells are counted from 1", s)),
+        }
+    }
 }
 
+impl game_controller::Move for CellIndex {}
+
 pub struct BaseStrategy<B: Board, CACHE: Cache<B>> {
     cache: CACHE,
     stats: NullStats,
@@ -110,3 +307,35 @@ pub fn default_score<S: BoardStatus>(status: S, player: Player) -> i32 {
         0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use crate::ttt::GameBoardMxN;
+
+    fn check_lines<const M: usize, const N: usize, const K: usize>(expected_lines: usize) {
+        type Board<const M: usize, const N: usize, const K: usize> = GameBoardMxN<M, N, K>;
+        assert_eq!(Board::<M, N, K>::LINES, expected_lines, "{}x{} board, {} in a row", M, N, K);
+        let lines = (0..Board::<M, N, K>::LINES).map(|line| Board::<M, N, K>::line_indices(line).collect_vec()).collect_vec();
+        assert!(lines.iter().all_unique());
+        for line in &lines {
+            let steps = line.iter().tuple_windows().map(|(a, b)| b - a).collect_vec();
+            assert!(steps.iter().all_equal() && line.iter().all(|index| *index < M * N), "{:?}", line);
+        }
+        for index in 0..M * N {
+            let through = Board::<M, N, K>::lines_through(index).sorted().collect_vec();
+            let containing = (0..lines.len()).filter(|line| lines[*line].contains(&index)).collect_vec();
+            assert_eq!(through, containing, "cell {} of a {}x{} board", index, M, N);
+        }
+    }
+
+    #[test]
+    fn lines_of_boards() {
+        check_lines::<3, 3, 3>(8);
+        // 12 rows, 10 columns, 6 diagonals each way
+        check_lines::<5, 4, 3>(34);
+        check_lines::<4, 5, 4>(5 + 8 + 2 + 2);
+        // too wide for columns and diagonals
+        check_lines::<4, 2, 3>(4);
+    }
+}
@@ -0,0 +1,573 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use rand::prelude::*;
+use crate::{common, fen, game_controller, min_max};
+use crate::common::{BaseStrategy, Board};
+use crate::fen::Fen;
+use crate::game_controller::{GameController, Replay, Status};
+use crate::min_max::{alpha_beta, Player, Strategy as _};
+use crate::min_max::cache::{Cache, HashMapCache};
+use crate::min_max::stats::NullStats;
+use crate::min_max::zobrist;
+use crate::min_max::zobrist::ZobristHash;
+use crate::min_max::symmetry::{CanonicalState, GridSymmetry7x6, GridSymmetryAxis, SymmetricMove, Symmetry};
+
+pub const COLUMNS: usize = 7;
+pub const ROWS: usize = 6;
+// every column has a spare bit on top, so shifting a line out of one column never reaches the next one
+const HEIGHT: usize = ROWS + 1;
+const COLUMN_MASK: u64 = (1 << ROWS) - 1;
+const BOTTOM_MASK: u64 = bottom_mask();
+const BOARD_MASK: u64 = BOTTOM_MASK * COLUMN_MASK;
+// columns from the center outwards, central columns take part in more lines
+const MOVE_ORDER: [usize; COLUMNS] = [3, 2, 4, 1, 5, 0, 6];
+// larger than any difference in threats
+const WIN_SCORE: i32 = 100;
+
+const fn bottom_mask() -> u64 {
+    let mut mask = 0;
+    let mut column = 0;
+    while column < COLUMNS {
+        mask |= 1 << (column * HEIGHT);
+        column += 1;
+    }
+    mask
+}
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub enum BoardStatus {
+    MaxWon,
+    MinWon,
+    Draw,
+    Ongoing,
+}
+
+impl common::BoardStatus for BoardStatus {
+    fn is_max_won(&self) -> bool {
+        matches!(self, BoardStatus::MaxWon)
+    }
+
+    fn is_min_won(&self) -> bool {
+        matches!(self, BoardStatus::MinWon)
+    }
+}
+
+/// One bitboard per player, bit `column * 7 + row` is the cell in `column` at `row` counted from the bottom.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct GameBoard {
+    max: u64,
+    min: u64,
+    pub last_player: Player,
+}
+
+impl GameBoard {
+    pub fn empty() -> Self {
+        Self { max: 0, min: 0, last_player: Player::Max }
+    }
+
+    fn occupied(&self) -> u64 {
+        self.max | self.min
+    }
+
+    fn stones(&self, player: Player) -> u64 {
+        match player {
+            Player::Max => self.max,
+            Player::Min => self.min,
+        }
+    }
+
+    /// Number of stones in `column`.
+    pub fn height(&self, column: usize) -> usize {
+        ((self.occupied() >> (column * HEIGHT)) & COLUMN_MASK).count_ones() as usize
+    }
+
+    pub fn is_playable(&self, column: usize) -> bool {
+        column < COLUMNS && self.height(column) < ROWS
+    }
+
+    /// The owner of the stone in `column` at `row` counted from the bottom.
+    pub fn cell(&self, column: usize, row: usize) -> Option<Player> {
+        let bit = 1 << (column * HEIGHT + row);
+        if self.max & bit != 0 {
+            Some(Player::Max)
+        } else if self.min & bit != 0 {
+            Some(Player::Min)
+        } else {
+            None
+        }
+    }
+
+    /// Drops a stone of `player` into `column`, which must not be full.
+    pub fn drop_stone(&self, column: usize, player: Player) -> Self {
+        debug_assert!(self.is_playable(column));
+        // adding the bottom bit of the column carries over to the lowest free cell
+        let landing = (self.occupied() + (1 << (column * HEIGHT))) & (COLUMN_MASK << (column * HEIGHT));
+        let mut new_state = *self;
+        match player {
+            Player::Max => new_state.max |= landing,
+            Player::Min => new_state.min |= landing,
+        }
+        new_state.last_player = player;
+        new_state
+    }
+
+    /// Left/right mirror symmetry, the other symmetries of the grid don't respect gravity.
+    pub fn symmetry(&self) -> GridSymmetry7x6 {
+        if mirror(self.max) == self.max && mirror(self.min) == self.min {
+            GridSymmetry7x6::new(GridSymmetryAxis::Vertical)
+        } else {
+            GridSymmetry7x6::none()
+        }
+    }
+
+    /// Number of empty cells that would complete four in a row for `player`, whether they can be played yet or not.
+    pub fn threats(&self, player: Player) -> u32 {
+        (winning_cells(self.stones(player)) & BOARD_MASK & !self.occupied()).count_ones()
+    }
+}
+
+fn mirror(stones: u64) -> u64 {
+    (0..COLUMNS).fold(0, |mirrored, column| {
+        let column_stones = (stones >> (column * HEIGHT)) & COLUMN_MASK;
+        mirrored | column_stones << ((COLUMNS - 1 - column) * HEIGHT)
+    })
+}
+
+// the shifts to the next cell vertically, horizontally and on both diagonals
+const DIRECTIONS: [usize; 4] = [1, HEIGHT, HEIGHT - 1, HEIGHT + 1];
+
+fn has_four(stones: u64) -> bool {
+    DIRECTIONS.iter().any(|shift| {
+        let pairs = stones & (stones >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
+
+// the cells that complete four in a row together with `stones`, may contain occupied and spare cells
+fn winning_cells(stones: u64) -> u64 {
+    // below three stones on top of each other
+    let mut cells = (stones << 1) & (stones << 2) & (stones << 3);
+    for shift in &DIRECTIONS[1..] {
+        let pairs = (stones << shift) & (stones << (2 * shift));
+        cells |= pairs & (stones << (3 * shift));
+        cells |= pairs & (stones >> shift);
+        let pairs = (stones >> shift) & (stones >> (2 * shift));
+        cells |= pairs & (stones << shift);
+        cells |= pairs & (stones >> (3 * shift));
+    }
+    cells
+}
+
+impl Board for GameBoard {
+    type Move = SymmetricMove<usize, GridSymmetry7x6>;
+    type BoardStatus = BoardStatus;
+
+    fn last_player(&self) -> Player {
+        self.last_player
+    }
+
+    fn status(&self) -> BoardStatus {
+        if has_four(self.max) {
+            BoardStatus::MaxWon
+        } else if has_four(self.min) {
+            BoardStatus::MinWon
+        } else if self.occupied() == BOARD_MASK {
+            BoardStatus::Draw
+        } else {
+            BoardStatus::Ongoing
+        }
+    }
+}
+
+impl CanonicalState for GameBoard {
+    /// The board or its mirror image, whichever has the smaller bitboards.
+    fn canonical_state(&self) -> Self {
+        let mirrored = Self { max: mirror(self.max), min: mirror(self.min), last_player: self.last_player };
+        if (mirrored.max, mirrored.min) < (self.max, self.min) { mirrored } else { *self }
+    }
+}
+
+impl ZobristHash for GameBoard {
+    fn zobrist_hash(&self) -> u64 {
+        let mut hash = zobrist::player_key(self.last_player);
+        for column in 0..COLUMNS {
+            for row in 0..self.height(column) {
+                let owner = self.cell(column, row).expect("the cells below the height are occupied");
+                hash ^= zobrist::key(column * ROWS + row, owner as usize);
+            }
+        }
+        hash
+    }
+}
+
+impl Display for GameBoard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for row in (0..ROWS).rev() {
+            write!(f, "|")?;
+            for column in 0..COLUMNS {
+                let symbol = match self.cell(column, row) {
+                    Some(Player::Max) => 'X',
+                    Some(Player::Min) => 'O',
+                    None => ' ',
+                };
+                write!(f, "{}|", symbol)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, " 1 2 3 4 5 6 7")
+    }
+}
+
+/// The rows from the top as in [GameBoard]'s `Display`, e.g. `7/7/7/7/7/3o3 x` after the first stone in the middle.
+impl Display for Fen<GameBoard> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let rows = (0..ROWS).rev().map(|row| (0..COLUMNS).map(move |column| self.0.cell(column, row).map(fen::side_symbol)));
+        fen::write_rows(f, rows)?;
+        write!(f, " {}", fen::side_symbol(!self.0.last_player))
+    }
+}
+
+impl FromStr for Fen<GameBoard> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [rows, side] = fen::fields(s)?;
+        let rows = fen::parse_rows(rows, COLUMNS, ROWS)?;
+        let mut board = GameBoard::empty();
+        // stones are dropped from the bottom up, so a stone above an empty cell can't be placed
+        for (row, cells) in rows.iter().rev().enumerate() {
+            for (column, cell) in cells.iter().enumerate() {
+                let Some(symbol) = cell else { continue };
+                let player = fen::parse_side(&symbol.to_string()).map_err(|_| format!("Invalid cell {}", symbol))?;
+                if board.height(column) != row {
+                    return Err(format!("The stone in column {} floats above an empty cell", column + 1));
+                }
+                board = board.drop_stone(column, player);
+            }
+        }
+        board.last_player = !fen::parse_side(side)?;
+        Ok(Fen(board))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    OutOfBounds(usize),
+    ColumnFull(usize),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is already over"),
+            MoveError::OutOfBounds(column) => write!(f, "Column {} does not exist", column + 1),
+            MoveError::ColumnFull(column) => write!(f, "Column {} is full", column + 1),
+        }
+    }
+}
+
+/// A move is the column a stone is dropped into, which is also the index of the top cell of that column in the grid
+/// the [GridSymmetry7x6] works on.
+pub type Move = SymmetricMove<usize, GridSymmetry7x6>;
+pub type Strategy<CACHE> = BaseStrategy<GameBoard, CACHE>;
+
+impl<CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
+    type State = GameBoard;
+    type Move = Move;
+    type MoveError = MoveError;
+    type Cache = CACHE;
+    type Stats = NullStats;
+
+    fn possible_moves(state: &GameBoard) -> impl IntoIterator<Item=Move> + 'static {
+        let state = *state;
+        let symmetry = state.symmetry();
+        let mut covered = [false; COLUMNS];
+        let game_over = state.status() != BoardStatus::Ongoing;
+        MOVE_ORDER.into_iter().filter_map(move |column| {
+            if game_over || !state.is_playable(column) {
+                return None;
+            }
+            let normalised = symmetry.canonicalize(&column);
+            if covered[normalised] {
+                return None;
+            }
+            covered[normalised] = true;
+            Some(SymmetricMove(normalised, symmetry.clone()))
+        })
+    }
+
+    fn do_move(&mut self, state: &GameBoard, SymmetricMove(column, _): &Move, player: Player) -> GameBoard {
+        state.drop_stone(*column, player)
+    }
+
+    fn try_do_move(&mut self, state: &GameBoard, m: &Move, player: Player) -> Result<GameBoard, MoveError> {
+        let column = *m.index();
+        if state.status() != BoardStatus::Ongoing {
+            Err(MoveError::GameOver)
+        } else if column >= COLUMNS {
+            Err(MoveError::OutOfBounds(column))
+        } else if !state.is_playable(column) {
+            Err(MoveError::ColumnFull(column))
+        } else {
+            Ok(self.do_move(state, m, player))
+        }
+    }
+
+    fn score(&mut self, state: &GameBoard, player: Player) -> i32 {
+        let score_for_max = match state.status() {
+            BoardStatus::MaxWon => WIN_SCORE,
+            BoardStatus::MinWon => -WIN_SCORE,
+            BoardStatus::Draw => 0,
+            BoardStatus::Ongoing => state.threats(Player::Max) as i32 - state.threats(Player::Min) as i32,
+        };
+        match player {
+            Player::Max => score_for_max,
+            Player::Min => -score_for_max,
+        }
+    }
+
+    fn cache(&mut self) -> &mut Self::Cache {
+        self.cache()
+    }
+
+    fn stats(&mut self) -> &mut Self::Stats {
+        self.stats()
+    }
+}
+
+/// The column of a move entered by the human player, counted from 1.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Column(pub usize);
+
+impl Display for Column {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(column @ 1..=COLUMNS) => Ok(Column(column - 1)),
+            _ => Err(format!("Invalid move {}, valid moves are 1 to {}", s, COLUMNS)),
+        }
+    }
+}
+
+impl game_controller::Move for Column {}
+
+impl game_controller::State for GameBoard {
+    fn player(&self) -> game_controller::Player {
+        match self.last_player {
+            Player::Max => game_controller::Player::Human,
+            Player::Min => game_controller::Player::Computer,
+        }
+    }
+
+    fn status(&self) -> Status {
+        match Board::status(self) {
+            BoardStatus::MaxWon => Status::Done { winner: game_controller::Player::Computer },
+            BoardStatus::MinWon => Status::Done { winner: game_controller::Player::Human },
+            BoardStatus::Draw => Status::Draw,
+            BoardStatus::Ongoing => Status::Playing,
+        }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> Replay for Strategy<CACHE> {
+    type Notation = Column;
+
+    fn initial() -> GameBoard {
+        GameBoard::empty()
+    }
+
+    fn play(&mut self, state: &GameBoard, Column(column): Column, player: Player) -> Result<GameBoard, String> {
+        self.try_do_move(state, &SymmetricMove(column, GridSymmetry7x6::none()), player).map_err(|e| e.to_string())
+    }
+
+    fn notations(_state: &GameBoard, m: &Move) -> Vec<Column> {
+        m.expanded_indices().into_iter().map(Column).collect()
+    }
+}
+
+/// The human plays [Player::Min] and moves first, the computer searches `depth` moves ahead.
+pub struct ConnectFour<CACHE: Cache<GameBoard> = HashMapCache<GameBoard>> {
+    rng: SmallRng,
+    strategy: Strategy<CACHE>,
+    depth: u8,
+}
+
+impl ConnectFour {
+    pub fn new(rng: SmallRng, depth: u8) -> Self {
+        Self::with_cache(rng, HashMapCache::default(), depth)
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> ConnectFour<CACHE> {
+    pub fn with_cache(rng: SmallRng, cache: CACHE, depth: u8) -> Self {
+        Self { rng, strategy: Strategy::new(cache), depth }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> GameController for ConnectFour<CACHE> {
+    type State = GameBoard;
+    type Move = Column;
+
+    fn initial(&mut self) -> Self::State {
+        Strategy::<CACHE>::initial()
+    }
+
+    fn do_move(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, Player::Min)
+    }
+
+    fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move) {
+        let moves = alpha_beta(&mut self.strategy, &mut state.clone(), self.depth);
+        let best = moves.choose(&mut self.rng).expect("the computer only moves while the game is ongoing");
+        let m = *Strategy::<CACHE>::notations(state, &best.min_max_move).choose(&mut self.rng).expect("a move expands to itself");
+        let state = self.strategy.play(state, m, Player::Max).expect("the search only finds legal moves");
+        (state, m)
+    }
+
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, game_controller::State::player(state).into())
+    }
+
+    fn hint(&mut self, state: &Self::State) -> String {
+        let mut scored_columns = game_controller::score_notations(&mut self.strategy, state, self.depth, Player::Min);
+        scored_columns.sort_by_key(|(column, _)| column.0);
+        game_controller::format_hint_list(&scored_columns)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use crate::common::Board;
+    use crate::game_controller::GameController;
+    use crate::min_max::{alpha_beta, score_possible_moves_for, Player, Strategy as _};
+    use crate::min_max::cache::{HashMapCache, SymmetricCache};
+    use super::*;
+
+    fn play(columns: &[usize]) -> GameBoard {
+        columns.iter().enumerate().fold(GameBoard::empty(), |state, (i, column)| {
+            state.drop_stone(*column, if i % 2 == 0 { Player::Min } else { Player::Max })
+        })
+    }
+
+    #[test]
+    fn status() {
+        assert_eq!(play(&[]).status(), BoardStatus::Ongoing);
+        // vertical
+        assert_eq!(play(&[0, 1, 0, 1, 0, 1, 0]).status(), BoardStatus::MinWon);
+        // horizontal
+        assert_eq!(play(&[0, 0, 1, 1, 2, 2]).status(), BoardStatus::Ongoing);
+        assert_eq!(play(&[0, 0, 1, 1, 2, 2, 3]).status(), BoardStatus::MinWon);
+        // diagonal
+        assert_eq!(play(&[6, 0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3]).status(), BoardStatus::MaxWon);
+        // a vertical line may not continue at the bottom of the next column
+        assert_eq!(play(&[1, 0, 6, 0, 6, 0, 0, 5, 0, 5, 0]).status(), BoardStatus::Ongoing);
+    }
+
+    #[test]
+    fn gravity() {
+        let state = play(&[3, 3, 3]);
+        assert_eq!(state.height(3), 3);
+        assert_eq!(state.cell(3, 0), Some(Player::Min));
+        assert_eq!(state.cell(3, 1), Some(Player::Max));
+        assert_eq!(state.cell(3, 3), None);
+
+        let full = play(&[0, 0, 0, 0, 0, 0]);
+        assert!(!full.is_playable(0));
+        let columns = Strategy::<HashMapCache<GameBoard>>::possible_moves(&full).into_iter().map(|m| m.0).collect_vec();
+        assert_eq!(columns, vec![3, 2, 4, 1, 5, 6]);
+    }
+
+    #[test]
+    fn mirror_symmetry() {
+        let columns = Strategy::<HashMapCache<GameBoard>>::possible_moves(&GameBoard::empty()).into_iter().map(|m| m.0).collect_vec();
+        assert_eq!(columns, vec![3, 2, 1, 0]);
+
+        let moves = Strategy::<HashMapCache<GameBoard>>::possible_moves(&play(&[3, 2, 4])).into_iter().collect_vec();
+        assert_eq!(moves.len(), COLUMNS);
+    }
+
+    #[test]
+    fn mirror_images_share_canonical_state() {
+        let (state, mirrored) = (play(&[0, 3, 1]), play(&[6, 3, 5]));
+        assert_ne!(state, mirrored);
+        assert_eq!(state.canonical_state(), mirrored.canonical_state());
+        assert!([state, mirrored].contains(&state.canonical_state()));
+
+        let player = !state.last_player;
+        let symmetric = score_possible_moves_for(&mut Strategy::new(SymmetricCache::new(HashMapCache::default())), &state, 6, player);
+        let plain = score_possible_moves_for(&mut Strategy::new(HashMapCache::default()), &state, 6, player);
+        assert_eq!(symmetric, plain);
+    }
+
+    #[test]
+    fn threats() {
+        // three in a row on the bottom with both ends open
+        let state = play(&[1, 1, 2, 2, 3]);
+        assert_eq!(state.threats(Player::Min), 2);
+        assert_eq!(state.threats(Player::Max), 0);
+        let mut strategy = Strategy::new(HashMapCache::default());
+        assert_eq!(strategy.score(&state, Player::Min), 2);
+    }
+
+    #[test]
+    fn finds_win_and_block() {
+        let mut strategy = Strategy::new(HashMapCache::default());
+        // Max to move and wins with the fourth stone in column 3
+        let mut state = play(&[0, 3, 0, 3, 1, 3, 6]);
+        let moves = alpha_beta(&mut strategy, &mut state, 6);
+        assert_eq!(moves.iter().map(|m| m.min_max_move.0).collect_vec(), vec![3]);
+
+        // Min threatens to complete the bottom row on either side, so Max can only block one end of it
+        let mut strategy = Strategy::new(HashMapCache::default());
+        let mut state = play(&[2, 2, 3]);
+        let moves = alpha_beta(&mut strategy, &mut state, 4);
+        assert!(moves.iter().all(|m| [1, 4].contains(&m.min_max_move.0)), "{:?}", moves.iter().map(|m| m.min_max_move.0).collect_vec());
+    }
+
+    #[test]
+    fn controller_rejects_full_column() {
+        let mut game = ConnectFour::new(SmallRng::seed_from_u64(0), 2);
+        let state = play(&[0, 0, 0, 0, 0, 0]);
+        assert!(game.do_move(&state, Column(0)).is_err());
+        assert!("8".parse::<Column>().is_err());
+        assert_eq!("1".parse::<Column>(), Ok(Column(0)));
+
+        let initial = game.initial();
+        let state = game.do_move(&initial, Column(3)).unwrap();
+        let (state, Column(column)) = game.do_computer_move(&state);
+        assert_eq!(state.height(column), if column == 3 { 2 } else { 1 });
+        assert_eq!(state.last_player, Player::Max);
+    }
+
+    #[test]
+    fn zobrist_hash_of_transpositions() {
+        use crate::min_max::zobrist::ZobristHash;
+        assert_eq!(play(&[0, 3, 1, 4]).zobrist_hash(), play(&[1, 4, 0, 3]).zobrist_hash());
+        assert_ne!(play(&[0, 3, 1, 4]).zobrist_hash(), play(&[3, 0, 4, 1]).zobrist_hash());
+        assert_ne!(play(&[0, 3, 1]).zobrist_hash(), play(&[0, 3, 1]).drop_stone(4, Player::Max).zobrist_hash());
+    }
+
+    #[test]
+    fn position_notation() {
+        let state = play(&[3, 3, 2]);
+        assert_eq!(Fen(state).to_string(), "7/7/7/7/3x3/2oo3 x");
+        assert_eq!("7/7/7/7/3x3/2oo3 x".parse(), Ok(Fen(state)));
+        assert_eq!(Fen(GameBoard::empty()).to_string(), "7/7/7/7/7/7 o");
+
+        let state = play(&[6, 0, 1, 1, 2, 2, 3, 2, 3, 3, 6, 3]);
+        assert_eq!(Fen(state).to_string().parse(), Ok(Fen(state)));
+        assert_eq!(
+            "7/7/7/3x3/7/7 o".parse::<Fen<GameBoard>>(),
+            Err("The stone in column 4 floats above an empty cell".to_string()),
+        );
+    }
+}
@@ -1,8 +1,13 @@
 use crate::min_max::cache::Cache;
+use crate::min_max::deepening::{deepen, DeepeningResult};
 use crate::min_max::stats::Stats;
-use crate::min_max::{Player, ScoredMove};
+use crate::min_max::{Player, ScoredLine, ScoredMove, SearchBudget, SearchConfig, SearchControl};
 use itertools::Itertools;
 use std::cmp::{max, min};
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
 
 pub enum Moves<Move, PMoves> {
     Player(PMoves),
@@ -12,13 +17,18 @@ pub enum Moves<Move, PMoves> {
 pub trait Strategy {
     type State;
     type Move;
+    /// Why [Strategy::try_do_move] rejected a move.
+    type MoveError: Display;
     type Cache: Cache<Self::State>;
     type Stats: Stats;
 
     fn possible_moves(
         state: &Self::State,
     ) -> Moves<Self::Move, impl IntoIterator<Item = Self::Move>>;
+    /// Plays a move of [Strategy::possible_moves], may panic for any other move.
     fn do_move(&mut self, state: &Self::State, _move: &Self::Move, player: Player) -> Self::State;
+    /// Plays any move, e.g. one entered by a human, if it is legal in `state`.
+    fn try_do_move(&mut self, state: &Self::State, _move: &Self::Move, player: Player) -> Result<Self::State, Self::MoveError>;
 
     fn score(&mut self, state: &Self::State, player: Player) -> i32;
 
@@ -44,39 +54,108 @@ pub fn score_possible_moves<STRATEGY: Strategy>(
     state: &STRATEGY::State,
     max_level: u8,
 ) -> Vec<ScoredMove<STRATEGY::Move>> {
-    let pos_moves = STRATEGY::possible_moves(&state);
-    match pos_moves {
-        Moves::Player(moves) => moves
-            .into_iter()
-            .map(|m| {
-                let next_state = strategy.do_move(state, &m, Player::Max);
-                let score = -alpha_beta_star_step(
-                    strategy,
-                    &next_state,
-                    Player::Min,
-                    max_level - 1,
-                    STRATEGY::lowest_score(),
-                    STRATEGY::highest_score(),
-                );
-                ScoredMove::new(score, m)
-            })
-            .collect(),
+    score_possible_moves_for(strategy, state, max_level, Player::Max)
+}
+
+/// Like [score_possible_moves] but for `player` to move instead of [Player::Max], e.g. to give the human a hint.
+pub fn score_possible_moves_for<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    max_level: u8,
+    player: Player,
+) -> Vec<ScoredMove<STRATEGY::Move>> {
+    score_lines(strategy, state, player, player_moves::<STRATEGY>(state), max_level, false, &mut SearchControl::unlimited())
+        .into_iter()
+        .map(ScoredMove::from)
+        .collect()
+}
+
+/// Searches with `max_level` 1, 2, 3, ... like [crate::min_max::deepening::iterative_deepening_with]: the search is
+/// aborted once the budget runs out or `stop` is set, and every completed iteration is passed to `on_iteration`. The
+/// principal variation ends at the first chance node, as no player chooses its outcome.
+///
+/// Returns `None` if not even the first iteration finished within the budget.
+pub fn iterative_deepening_with<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    budget: SearchBudget,
+    max_level: u8,
+    stop: Arc<AtomicBool>,
+    mut on_iteration: impl FnMut(&DeepeningResult<STRATEGY::Move>),
+) -> Option<DeepeningResult<STRATEGY::Move>> where STRATEGY::Move: Clone {
+    let moves = player_moves::<STRATEGY>(state);
+    let score = |strategy: &mut STRATEGY, moves, depth, control: &mut SearchControl| score_lines(strategy, state, player, moves, depth, true, control);
+    deepen(strategy, moves, budget, max_level, Some(stop), SearchConfig::default(), score, |_, iteration| on_iteration(iteration))
+}
+
+fn player_moves<STRATEGY: Strategy>(state: &STRATEGY::State) -> Vec<STRATEGY::Move> {
+    match STRATEGY::possible_moves(state) {
+        Moves::Player(moves) => moves.into_iter().collect(),
         Moves::Chance(_) => panic!("Chance must be resolved before finding optimal move"),
     }
 }
 
+// Scores the given root moves of `player` in order. If the search is aborted the returned scores are meaningless.
+fn score_lines<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    moves: Vec<STRATEGY::Move>,
+    max_level: u8,
+    collect_pv: bool,
+    control: &mut SearchControl,
+) -> Vec<ScoredLine<STRATEGY::Move>> {
+    let start = Instant::now();
+    let lines = moves.into_iter().map(|m| {
+        let next_state = strategy.do_move(state, &m, player);
+        let mut pv = Vec::new();
+        let pv_out = if collect_pv { Some(&mut pv) } else { None };
+        let score = -alpha_beta_star_step(
+            strategy,
+            &next_state,
+            !player,
+            max_level.saturating_sub(1),
+            1,
+            STRATEGY::lowest_score(),
+            STRATEGY::highest_score(),
+            pv_out,
+            control,
+        );
+        ScoredLine { score, min_max_move: m, principal_variation: pv }
+    }).collect();
+    strategy.stats().record_search_time(start.elapsed());
+    lines
+}
+
+fn score_leaf<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, player: Player) -> i32 {
+    strategy.stats().record_state_scored();
+    strategy.score(state, player)
+}
+
 // The *-Minimax Search Procedure for Trees Containing Chance Nodes - Section 5
 // https://www.cs.uleth.ca/~benkoczi/3750/data/ballard83-star_alpha_beta.pdf
+// `ply` is the distance from the searched state and only used for statistics. If `pv` is given it receives the best
+// line found from `state` on, up to the next chance node.
+#[allow(clippy::too_many_arguments)]
 fn alpha_beta_star_step<STRATEGY: Strategy>(
     strategy: &mut STRATEGY,
     state: &STRATEGY::State,
     player: Player,
     remaining_levels: u8,
+    ply: u8,
     mut alpha: i32,
     beta: i32,
+    mut pv: Option<&mut Vec<STRATEGY::Move>>,
+    control: &mut SearchControl,
 ) -> i32 {
+    if !control.enter_node() {
+        return 0;
+    }
+    strategy.stats().record_node(ply);
     if remaining_levels == 0 {
-        return strategy.score(state, player);
+        control.reached_horizon = true;
+        return score_leaf(strategy, state, player);
     }
 
     let moves = STRATEGY::possible_moves(state);
@@ -85,22 +164,41 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
             let mut moves = moves.into_iter().peekable();
             // Check if this state is terminal i.e. no more moves can be made
             if moves.peek().is_none() {
-                return strategy.score(state, player);
+                return score_leaf(strategy, state, player);
             }
 
             let mut max_score = -i32::MAX;
-            for m in moves {
+            let mut child_pv = pv.as_ref().map(|_| Vec::new());
+            for (index, m) in moves.enumerate() {
                 let next_state = strategy.do_move(state, &m, player);
-                max_score = max_score.max(-alpha_beta_star_step(
+                if let Some(child_pv) = child_pv.as_mut() {
+                    child_pv.clear();
+                }
+                let score = -alpha_beta_star_step(
                     strategy,
                     &next_state,
                     !player,
                     remaining_levels - 1,
+                    ply + 1,
                     -beta,
                     -alpha,
-                ));
+                    child_pv.as_mut(),
+                    control,
+                );
+                if control.aborted {
+                    return 0;
+                }
+                if score > max_score {
+                    max_score = score;
+                    if let (Some(pv), Some(child_pv)) = (pv.as_mut(), child_pv.as_mut()) {
+                        pv.clear();
+                        pv.push(m);
+                        pv.append(child_pv);
+                    }
+                }
                 alpha = alpha.max(max_score);
                 if alpha >= beta {
+                    strategy.stats().record_prune(index);
                     break;
                 }
             }
@@ -108,7 +206,7 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
         }
         Moves::Chance(moves) => {
             if moves.is_empty() {
-                return strategy.score(state, player);
+                return score_leaf(strategy, state, player);
             }
             let n = moves.len() as i32;
 
@@ -117,13 +215,17 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
 
             let mut states = vec![];
             let mut probe_scores = vec![];
-            for m in moves {
+            for (index, m) in moves.into_iter().enumerate() {
                 let next_state = strategy.do_move(state, &m, player);
                 a += STRATEGY::highest_score();
                 let ax = max(a, STRATEGY::lowest_score());
                 let bx = min(b, STRATEGY::highest_score());
-                let probe_score = probe(strategy, &next_state, player, remaining_levels - 1, ax, bx);
+                let probe_score = probe(strategy, &next_state, player, remaining_levels - 1, ply + 1, ax, bx, control);
+                if control.aborted {
+                    return 0;
+                }
                 if probe_score <= a {
+                    strategy.stats().record_prune(index);
                     return alpha;
                 }
                 a -= probe_score;
@@ -132,7 +234,7 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
             }
 
             let mut sum = 0;
-            for (next_state, probe_score) in states.iter().zip(probe_scores) {
+            for (index, (next_state, probe_score)) in states.iter().zip(probe_scores).enumerate() {
                 b += STRATEGY::lowest_score();
                 a += probe_score;
                 // Limit child α, β to n valid range
@@ -144,14 +246,22 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
                     next_state,
                     player,
                     remaining_levels - 1,
+                    ply + 1,
                     ax,
                     bx,
+                    None,
+                    control,
                 );
+                if control.aborted {
+                    return 0;
+                }
                 // Check for α, β cutoff conditions
                 if score <= n {
+                    strategy.stats().record_prune(index);
                     return alpha;
                 }
                 if score >= b {
+                    strategy.stats().record_prune(index);
                     return beta;
                 }
                 sum += score;
@@ -165,22 +275,30 @@ fn alpha_beta_star_step<STRATEGY: Strategy>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn probe<STRATEGY: Strategy>(
     strategy: &mut STRATEGY,
     state: &STRATEGY::State,
     player: Player,
     remaining_levels: u8,
+    ply: u8,
     alpha: i32,
     beta: i32,
+    control: &mut SearchControl,
 ) -> i32 {
+    if !control.enter_node() {
+        return 0;
+    }
+    strategy.stats().record_node(ply);
     if remaining_levels == 0 {
-        return strategy.score(state, player);
+        control.reached_horizon = true;
+        return score_leaf(strategy, state, player);
     }
 
     let moves = STRATEGY::possible_moves(state);
     match moves {
         Moves::Player(moves) => match moves.into_iter().next() {
-            None => strategy.score(state, player),
+            None => score_leaf(strategy, state, player),
             Some(m) => {
                 let next_state = strategy.do_move(state, &m, player);
                 alpha_beta_star_step(
@@ -188,8 +306,11 @@ fn probe<STRATEGY: Strategy>(
                     &next_state,
                     player,
                     remaining_levels - 1,
+                    ply + 1,
                     alpha,
                     beta,
+                    None,
+                    control,
                 )
             }
         },
@@ -0,0 +1,131 @@
+use std::fmt::{Formatter, Write};
+use std::iter;
+use crate::min_max::Player;
+
+/// A position in a compact notation like FEN in chess, written by `Display` and read by `FromStr`, e.g. `x2/1o1/3 o`
+/// for tic-tac-toe. The cells come row by row from the top, separated by `/`, where a number stands for that many
+/// empty cells. The side to move follows, `x` for [Player::Max] and `o` for [Player::Min] like the marks of
+/// tic-tac-toe, and then whatever else a game needs to go on, e.g. the last move in ultimate tic-tac-toe.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Fen<S>(pub S);
+
+/// Writes `rows` of cells, `None` is an empty cell.
+pub fn write_rows<R: IntoIterator<Item=Option<char>>>(f: &mut Formatter<'_>, rows: impl IntoIterator<Item=R>) -> std::fmt::Result {
+    for (index, row) in rows.into_iter().enumerate() {
+        if index > 0 {
+            f.write_char('/')?;
+        }
+        let mut empty = 0;
+        for cell in row {
+            match cell {
+                None => empty += 1,
+                Some(symbol) => {
+                    if empty > 0 {
+                        write!(f, "{}", empty)?;
+                        empty = 0;
+                    }
+                    f.write_char(symbol)?;
+                }
+            }
+        }
+        if empty > 0 {
+            write!(f, "{}", empty)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads `rows` rows of `columns` cells each as written by [write_rows].
+pub fn parse_rows(s: &str, columns: usize, rows: usize) -> Result<Vec<Vec<Option<char>>>, String> {
+    let parsed = s.split('/').map(|row| parse_row(row, columns)).collect::<Result<Vec<_>, _>>()?;
+    if parsed.len() != rows {
+        return Err(format!("Expected {} rows instead of {} in {}", rows, parsed.len(), s));
+    }
+    Ok(parsed)
+}
+
+fn parse_row(row: &str, columns: usize) -> Result<Vec<Option<char>>, String> {
+    let too_long = || format!("Row {} has more than {} cells", row, columns);
+    let mut cells = vec![];
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c.to_digit(10) {
+            Some(digit) => {
+                let mut empty = digit as usize;
+                while let Some(digit) = chars.next_if(char::is_ascii_digit).and_then(|digit| digit.to_digit(10)) {
+                    empty = empty.saturating_mul(10).saturating_add(digit as usize);
+                }
+                if cells.len().saturating_add(empty) > columns {
+                    return Err(too_long());
+                }
+                cells.extend(iter::repeat_n(None, empty));
+            }
+            None if cells.len() < columns => cells.push(Some(c)),
+            None => return Err(too_long()),
+        }
+    }
+    if cells.len() < columns {
+        return Err(format!("Row {} has {} cells instead of {}", row, cells.len(), columns));
+    }
+    Ok(cells)
+}
+
+pub fn side_symbol(player: Player) -> char {
+    match player {
+        Player::Max => 'x',
+        Player::Min => 'o',
+    }
+}
+
+pub fn parse_side(s: &str) -> Result<Player, String> {
+    match s {
+        "x" => Ok(Player::Max),
+        "o" => Ok(Player::Min),
+        _ => Err(format!("Invalid side to move {}, it is x or o", s)),
+    }
+}
+
+/// Splits `s` into the `N` fields of a notation, which are separated by whitespace.
+pub fn fields<const N: usize>(s: &str) -> Result<[&str; N], String> {
+    let fields: Vec<_> = s.split_whitespace().collect();
+    fields.try_into().map_err(|fields: Vec<_>| format!("Expected {} fields instead of {} in {}", N, fields.len(), s))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Display;
+    use super::*;
+
+    struct Rows(Vec<Vec<Option<char>>>);
+
+    impl Display for Rows {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write_rows(f, self.0.iter().map(|row| row.iter().copied()))
+        }
+    }
+
+    #[test]
+    fn rows_round_trip() {
+        let rows = vec![vec![Some('x'), None, None], vec![None; 3], vec![None, Some('o'), Some('x')]];
+        assert_eq!(Rows(rows.clone()).to_string(), "x2/3/1ox");
+        assert_eq!(parse_rows("x2/3/1ox", 3, 3), Ok(rows));
+        assert_eq!(parse_rows("12/12", 12, 2), Ok(vec![vec![None; 12]; 2]));
+    }
+
+    #[test]
+    fn invalid_rows() {
+        assert_eq!(parse_rows("3/3", 3, 3), Err("Expected 3 rows instead of 2 in 3/3".to_string()));
+        assert_eq!(parse_rows("3/x3/3", 3, 3), Err("Row x3 has more than 3 cells".to_string()));
+        assert_eq!(parse_rows("3/xo/3", 3, 3), Err("Row xo has 2 cells instead of 3".to_string()));
+        assert!(parse_rows("99999999999999999999999/3/3", 3, 3).is_err());
+    }
+
+    #[test]
+    fn sides_and_fields() {
+        assert_eq!(parse_side(&side_symbol(Player::Max).to_string()), Ok(Player::Max));
+        assert_eq!(parse_side("o"), Ok(Player::Min));
+        assert!(parse_side("w").is_err());
+        assert_eq!(fields::<2>(" 3/3/3  o "), Ok(["3/3/3", "o"]));
+        assert_eq!(fields::<2>("3/3/3"), Err("Expected 2 fields instead of 1 in 3/3/3".to_string()));
+    }
+}
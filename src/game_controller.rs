@@ -2,13 +2,24 @@ use std::fmt::Display;
 use std::io;
 use std::io::Write;
 use std::str::FromStr;
+use crate::{min_max, record};
+use crate::record::Record;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Player {
     Human,
     Computer,
 }
 
+impl From<Player> for min_max::Player {
+    fn from(player: Player) -> Self {
+        match player {
+            Player::Human => min_max::Player::Min,
+            Player::Computer => min_max::Player::Max,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Status {
     Playing,
@@ -19,9 +30,13 @@ pub enum Status {
 pub trait State: Display {
     fn player(&self) -> Player;
     fn status(&self) -> Status;
+    /// The outcome of chance in this state, e.g. the dice roll to place. Records keep it to replay games exactly.
+    fn chance(&self) -> Option<String> {
+        None
+    }
 }
 
-pub trait Move: Display + FromStr where <Self as FromStr>::Err: Display {}
+pub trait Move: Display + FromStr + Clone where <Self as FromStr>::Err: Display {}
 
 pub trait GameController where <Self::Move as FromStr>::Err: Display {
     type State: State;
@@ -30,18 +45,231 @@ pub trait GameController where <Self::Move as FromStr>::Err: Display {
     fn initial(&mut self) -> Self::State;
     fn do_move(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String>;
     fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move);
+    /// Plays `m` for whoever is to move in `state`, to follow the moves of a saved game.
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String>;
+    /// Lets chance play out as `outcomes`, as given by [State::chance], before it is random again.
+    fn script_chance(&mut self, _outcomes: &[&str]) -> Result<(), String> {
+        Ok(())
+    }
+    /// Scores every move the human can play in `state`, for the `hint` command.
+    fn hint(&mut self, state: &Self::State) -> String;
+}
+
+/// A [min_max::Strategy] whose positions can be set up from moves in the notation the human player enters them in.
+pub trait Replay: min_max::Strategy where <Self::Notation as FromStr>::Err: Display {
+    type Notation: Move;
+
+    fn initial() -> Self::State;
+    /// Plays `m` for `player` if it is legal in `state`.
+    fn play(&mut self, state: &Self::State, m: Self::Notation, player: min_max::Player) -> Result<Self::State, String>;
+    /// The legal moves in `state` the move `m` of the search stands for, e.g. all cells that are equal to it under
+    /// symmetry.
+    fn notations(state: &Self::State, m: &Self::Move) -> Vec<Self::Notation>;
+}
+
+/// Scores every move `player` can play in `state` and expands them to their notations.
+pub fn score_notations<R: Replay>(strategy: &mut R, state: &R::State, max_level: u8, player: min_max::Player) -> Vec<(R::Notation, i32)>
+where <R::Notation as FromStr>::Err: Display {
+    min_max::score_possible_moves_for(strategy, state, max_level, player).iter()
+        .flat_map(|m| R::notations(state, &m.min_max_move).into_iter().map(move |n| (n, m.score)))
+        .collect()
+}
+
+/// Formats scored moves one per line, the best ones are marked by `*`.
+pub fn format_hint_list<M: Display>(scored_moves: &[(M, i32)]) -> String {
+    let best = scored_moves.iter().map(|(_, score)| *score).max();
+    scored_moves.iter()
+        .map(|(m, score)| format!("{}: {}{}\n", m, score, if Some(*score) == best { " *" } else { "" }))
+        .collect()
+}
+
+/// Formats scored cells of a 3x3 board like [min_max::print_3_by_3]. The best cells are marked by `*`, cells that
+/// can't be played are shown as `.`.
+pub fn format_hint_grid(scored_cells: &[(usize, i32)]) -> String {
+    let best = scored_cells.iter().map(|(_, score)| *score).max();
+    let mut grid: [String; 9] = Default::default();
+    grid.iter_mut().for_each(|cell| *cell = ".".to_string());
+    for (index, score) in scored_cells {
+        grid[*index] = format!("{}{}", score, if Some(*score) == best { "*" } else { "" });
+    }
+    min_max::format_3_by_3(&grid)
+}
+
+/// The moves of a game together with the states they were played in. Undone moves are kept until a different move is
+/// played, so they can be redone.
+#[derive(Debug)]
+pub struct History<S, M> {
+    // states[0] is the initial state, states[i + 1] the state after moves[i]
+    states: Vec<S>,
+    moves: Vec<(M, Player)>,
+    // number of moves that are currently played
+    position: usize,
+}
+
+impl<S, M> History<S, M> {
+    pub fn new(initial: S) -> Self {
+        Self { states: vec![initial], moves: vec![], position: 0 }
+    }
+
+    pub fn initial(&self) -> &S {
+        &self.states[0]
+    }
+
+    pub fn state(&self) -> &S {
+        &self.states[self.position]
+    }
+
+    /// The moves that are currently played, oldest first.
+    pub fn moves(&self) -> &[(M, Player)] {
+        &self.moves[..self.position]
+    }
+
+    /// Plays `m`, which led to `state`. Drops the moves that could have been redone.
+    pub fn push(&mut self, m: M, player: Player, state: S) {
+        self.states.truncate(self.position + 1);
+        self.moves.truncate(self.position);
+        self.states.push(state);
+        self.moves.push((m, player));
+        self.position += 1;
+    }
+
+    /// Takes back the last move of the human and the computer moves after it. Returns `false` if the human didn't
+    /// move yet.
+    pub fn undo(&mut self) -> bool {
+        match self.moves().iter().rposition(|(_, player)| *player == Player::Human) {
+            Some(index) => {
+                self.position = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Plays the next undone move of the human again and the computer moves after it. Returns `false` if there is
+    /// nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        if self.position == self.moves.len() {
+            return false;
+        }
+        self.position += 1;
+        while self.position < self.moves.len() && self.moves[self.position].1 == Player::Computer {
+            self.position += 1;
+        }
+        true
+    }
+}
+
+impl<S: State, M: Display> History<S, M> {
+    /// The moves that are currently played with the tags of `header`. Players and Result are filled in, as well as
+    /// Chance for games with chance.
+    pub fn to_record(&self, header: &Record) -> Record {
+        let mut record = header.clone();
+        record.set_tag("Players", match self.initial().player() {
+            Player::Human => "Human - Computer",
+            Player::Computer => "Computer - Human",
+        });
+        record.set_tag("Result", match self.state().status() {
+            Status::Playing => record::ONGOING,
+            Status::Draw => record::DRAW,
+            Status::Done { winner } if winner == self.initial().player() => record::FIRST_WON,
+            Status::Done { .. } => record::SECOND_WON,
+        });
+        let chance = self.states[..=self.position].iter().filter_map(State::chance).collect::<Vec<_>>();
+        if !chance.is_empty() {
+            record.set_tag("Chance", chance.join(" "));
+        }
+        record.moves = self.moves().iter().map(|(m, _)| m.to_string()).collect();
+        record
+    }
 }
 
-pub fn game_loop<GAME: GameController>(game: &mut GAME) {
-    let mut state = game.initial();
-    while matches!(state.status(), Status::Playing) {
-        println!("{}", state);
-        match state.player() {
+/// Plays the moves of `record` from the initial state on.
+pub fn load<GAME: GameController>(game: &mut GAME, record: &Record) -> Result<History<GAME::State, GAME::Move>, String> {
+    if let Some(chance) = record.tag("Chance") {
+        game.script_chance(&chance.split_whitespace().collect::<Vec<_>>())?;
+    }
+    let mut history = History::new(game.initial());
+    for (i, notation) in record.moves.iter().enumerate() {
+        let invalid = |e: String| format!("Move {} ({}) of the record is invalid: {}", i + 1, notation, e);
+        let m = notation.parse::<GAME::Move>().map_err(|e| invalid(e.to_string()))?;
+        let player = history.state().player();
+        let state = game.play(history.state(), m.clone()).map_err(invalid)?;
+        history.push(m, player, state);
+    }
+    Ok(history)
+}
+
+impl<S, M: Display> Display for History<S, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (m, player)) in self.moves[..self.position].iter().enumerate() {
+            let name = match player {
+                Player::Human => "You",
+                Player::Computer => "Computer",
+            };
+            writeln!(f, "{}. {}: {}", i + 1, name, m)?;
+        }
+        if self.position < self.moves.len() {
+            writeln!(f, "({} moves can be redone)", self.moves.len() - self.position)?;
+        }
+        Ok(())
+    }
+}
+
+enum Input<M> {
+    Move(M),
+    Undo,
+    Redo,
+    History,
+    Hint,
+    Save(String),
+}
+
+/// Plays the game on from the moves of `record`. The game is saved with the tags of `record`.
+pub fn game_loop<GAME: GameController>(game: &mut GAME, record: &Record) -> Result<(), String> {
+    let mut history = load(game, record)?;
+    while matches!(history.state().status(), Status::Playing) {
+        println!("{}", history.state());
+        match history.state().player() {
             Player::Human => loop {
-                let _move = read_move();
-                match game.do_move(&state, _move) {
+                let _move: GAME::Move = match read_input() {
+                    None => {
+                        println!("Game aborted");
+                        return Ok(());
+                    }
+                    Some(Input::Move(m)) => m,
+                    Some(Input::Undo) => {
+                        if history.undo() {
+                            break;
+                        }
+                        println!("Error: Nothing to undo");
+                        continue;
+                    }
+                    Some(Input::Redo) => {
+                        if history.redo() {
+                            break;
+                        }
+                        println!("Error: Nothing to redo");
+                        continue;
+                    }
+                    Some(Input::History) => {
+                        print!("{}", history);
+                        continue;
+                    }
+                    Some(Input::Hint) => {
+                        print!("{}", game.hint(history.state()));
+                        continue;
+                    }
+                    Some(Input::Save(path)) => {
+                        match history.to_record(record).save(&path) {
+                            Ok(()) => println!("Saved to {}", path),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                        continue;
+                    }
+                };
+                match game.do_move(history.state(), _move.clone()) {
                     Ok(new_state) => {
-                        state = new_state;
+                        history.push(_move, Player::Human, new_state);
                         break;
                     }
                     Err(e) => {
@@ -50,15 +278,15 @@ pub fn game_loop<GAME: GameController>(game: &mut GAME) {
                 }
             },
             Player::Computer => {
-                let (new_state, _move) = game.do_computer_move(&state);
-                state = new_state;
+                let (new_state, _move) = game.do_computer_move(history.state());
                 println!("Computer move: {}", _move);
+                history.push(_move, Player::Computer, new_state);
             }
         }
     }
     println!(
         "Game ended: {}",
-        match state.status() {
+        match history.state().status() {
             Status::Playing => unreachable!(),
             Status::Draw => "Draw",
             Status::Done {
@@ -69,19 +297,155 @@ pub fn game_loop<GAME: GameController>(game: &mut GAME) {
             } => "You Lost!",
         }
     );
+    Ok(())
+}
+
+/// Steps through the moves of `record` for review, forwards with an empty line or next and backwards with back.
+pub fn replay_loop<GAME: GameController>(game: &mut GAME, record: &Record) -> Result<(), String> {
+    let history = load(game, record)?;
+    let moves = history.moves();
+    let mut position = 0;
+    loop {
+        println!("{}", history.states[position]);
+        match position {
+            0 => println!("Start, {} moves", moves.len()),
+            _ => println!("Move {}/{}: {}", position, moves.len(), moves[position - 1].0),
+        }
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let mut buffer = String::new();
+        if io::stdin().read_line(&mut buffer).unwrap() == 0 {
+            return Ok(());
+        }
+        match buffer.trim() {
+            "" | "next" if position < moves.len() => position += 1,
+            "" | "next" => println!("Error: This is the last move"),
+            "back" if position > 0 => position -= 1,
+            "back" => println!("Error: This is the start"),
+            "quit" => return Ok(()),
+            input => println!("Error: Unknown command {}, valid commands are next, back and quit", input),
+        }
+    }
 }
 
-fn read_move<M: Move>() -> M where <M as FromStr>::Err: Display {
+// `None` once the input is closed
+fn read_input<M: Move>() -> Option<Input<M>> where <M as FromStr>::Err: Display {
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
         let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        match M::from_str(&buffer.trim()) {
-            Ok(m) => return m,
-            Err(e) => {
-                println!("Error: {}", e);
-            }
+        if io::stdin().read_line(&mut buffer).unwrap() == 0 {
+            return None;
         }
+        match buffer.trim() {
+            "undo" => return Some(Input::Undo),
+            "redo" => return Some(Input::Redo),
+            "history" => return Some(Input::History),
+            "hint" => return Some(Input::Hint),
+            input if input.starts_with("save ") => return Some(Input::Save(input["save ".len()..].trim().to_string())),
+            input => match M::from_str(input) {
+                Ok(m) => return Some(Input::Move(m)),
+                Err(e) => {
+                    println!("Error: {}", e);
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // states are the number of moves played, moves their notation
+    fn history(players: &[Player]) -> History<usize, String> {
+        let mut history = History::new(0);
+        for (i, player) in players.iter().enumerate() {
+            history.push(format!("m{}", i + 1), *player, i + 1);
+        }
+        history
+    }
+
+    #[test]
+    fn undo_takes_back_computer_replies() {
+        use Player::*;
+        let mut history = history(&[Human, Computer, Human, Computer]);
+        assert!(history.undo());
+        assert_eq!(*history.state(), 2);
+        assert!(history.undo());
+        assert_eq!(*history.state(), 0);
+        assert!(!history.undo());
+
+        assert!(history.redo());
+        assert_eq!(*history.state(), 2);
+        assert_eq!(history.to_string(), "1. You: m1\n2. Computer: m2\n(2 moves can be redone)\n");
+        assert!(history.redo());
+        assert_eq!(*history.state(), 4);
+        assert!(!history.redo());
+    }
+
+    #[test]
+    fn hint_marks_best_moves() {
+        assert_eq!(format_hint_list(&[("a", 1), ("b", 3), ("c", 3)]), "a: 1\nb: 3 *\nc: 3 *\n");
+        assert_eq!(format_hint_grid(&[(0, -2), (4, 5), (8, 0)]), " -2,   .,   .\n  .,  5*,   .\n  .,   .,   0\n");
+    }
+
+    #[test]
+    fn load_and_save_record() {
+        use rand::SeedableRng;
+        use crate::ttt::TicTacToe;
+
+        let mut game = TicTacToe::new(rand::rngs::SmallRng::seed_from_u64(0), u8::MAX);
+        let mut header = Record::new();
+        header.set_tag("Game", "ttt");
+        let mut record = header.clone();
+        record.moves = ["5", "1", "3", "9"].map(String::from).to_vec();
+        let history = load(&mut game, &record).unwrap();
+        assert_eq!(history.moves().len(), 4);
+        assert_eq!(history.state().player(), Player::Human);
+
+        let saved = history.to_record(&header);
+        assert_eq!(saved.tag("Players"), Some("Human - Computer"));
+        assert_eq!(saved.tag("Result"), Some(record::ONGOING));
+        assert_eq!(saved.moves, record.moves);
+
+        record.moves.push("7".to_string());
+        assert_eq!(load(&mut game, &record).unwrap().to_record(&header).tag("Result"), Some(record::FIRST_WON));
+        record.moves.push("4".to_string());
+        assert!(load(&mut game, &record).is_err());
+        record.moves = vec!["5".to_string(), "5".to_string()];
+        assert_eq!(load(&mut game, &record).err(), Some("Move 2 (5) of the record is invalid: Cell 5 is already taken".to_string()));
+    }
+
+    #[test]
+    fn records_keep_chance() {
+        use crate::knucklebones::Knucklebones;
+
+        let mut header = Record::new();
+        header.set_tag("Game", "knucklebones");
+        let mut record = header.clone();
+        record.moves = ["1", "2", "3", "1"].map(String::from).to_vec();
+        let history = load(&mut Knucklebones::with_seed(1, 2), &record).unwrap();
+        let saved = history.to_record(&header);
+        assert_eq!(saved.tag("Chance").map(|chance| chance.split(' ').count()), Some(5));
+
+        // the dice of the record are rolled no matter the seed
+        let replayed = load(&mut Knucklebones::with_seed(2, 2), &saved).unwrap();
+        assert_eq!(replayed.state(), history.state());
+        assert_eq!(replayed.to_record(&header), saved);
+    }
+
+    #[test]
+    fn push_drops_undone_moves() {
+        use Player::*;
+        let mut history = history(&[Computer, Human, Computer]);
+        assert!(history.undo());
+        assert_eq!(*history.state(), 1);
+        assert!(!history.undo());
+
+        history.push("other".to_string(), Human, 10);
+        assert_eq!(*history.state(), 10);
+        assert_eq!(history.moves(), [("m1".to_string(), Computer), ("other".to_string(), Human)]);
+        assert!(!history.redo());
     }
 }
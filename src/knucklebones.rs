@@ -1,12 +1,17 @@
 use crate::expecti_min_max::{alpha_beta_star, Moves, Strategy as StrategyTrait};
 use crate::game_controller::{GameController, Status};
 use crate::min_max::cache::NullCache;
-use crate::min_max::stats::NullStats;
+use crate::min_max::stats::SimpleStats;
 use crate::min_max::Player;
-use crate::{expecti_min_max, game_controller};
+use crate::min_max::zobrist;
+use crate::min_max::zobrist::ZobristHash;
+use crate::{expecti_min_max, fen, game_controller};
+use crate::fen::Fen;
+use itertools::Itertools;
 use rand::distr::StandardUniform;
 use rand::prelude::*;
 use std::fmt::{write, Display, Formatter, Write};
+use std::collections::VecDeque;
 use std::str::FromStr;
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
@@ -59,6 +64,22 @@ impl Distribution<DiceRoll> for StandardUniform {
     }
 }
 
+impl FromStr for DiceRoll {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(DiceRoll::One),
+            "2" => Ok(DiceRoll::Two),
+            "3" => Ok(DiceRoll::Three),
+            "4" => Ok(DiceRoll::Four),
+            "5" => Ok(DiceRoll::Five),
+            "6" => Ok(DiceRoll::Six),
+            _ => Err(format!("Invalid dice roll {}, valid rolls are 1 to 6", s)),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub enum Cell {
     Empty,
@@ -111,18 +132,20 @@ impl Row {
         score
     }
 
-    fn add(&self, roll: DiceRoll) -> Row {
+    /// `None` if the row is full.
+    fn add(&self, roll: DiceRoll) -> Option<Row> {
         if self.0[0] != Cell::Empty {
-            panic!()
+            return None;
         }
-        let mut cells = self.0.clone();
+        let mut cells = self.0;
         cells[0] = Cell::Dice(roll);
         cells.sort();
-        Row(cells)
+        Some(Row(cells))
     }
 
+    /// Removes all dice showing `roll`, rows without such a dice stay the same.
     fn remove(&self, roll: DiceRoll) -> Row {
-        let mut cells = self.0.clone();
+        let mut cells = self.0;
         for cell in cells.iter_mut() {
             if *cell == Cell::Dice(roll) {
                 *cell = Cell::Empty;
@@ -133,7 +156,12 @@ impl Row {
     }
 
     fn is_full(&self) -> bool {
-        !self.0.iter().any(|&cell| cell == Cell::Empty)
+        !self.0.contains(&Cell::Empty)
+    }
+
+    // contribution of the row at `index` of the cells, counted over both sides, to the Zobrist hash of the state
+    fn zobrist_hash(&self, index: usize) -> u64 {
+        self.0.iter().enumerate().fold(0, |hash, (cell_index, cell)| hash ^ zobrist::key(index * 3 + cell_index, cell.as_i32() as usize))
     }
 }
 
@@ -158,7 +186,7 @@ impl Side {
                 if i == row as usize {
                     update(&self.rows[i])
                 } else {
-                    self.rows[i].clone()
+                    self.rows[i]
                 }
             }),
         }
@@ -167,6 +195,11 @@ impl Side {
     fn is_full(&self) -> bool {
         self.rows.iter().all(|row| row.is_full())
     }
+
+    // contribution of the side to the Zobrist hash, `offset` is the index of its first row over both sides
+    fn zobrist_hash(&self, offset: usize) -> u64 {
+        self.rows.iter().enumerate().fold(0, |hash, (index, row)| hash ^ row.zobrist_hash(offset + index))
+    }
 }
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
@@ -175,16 +208,88 @@ pub struct State {
     min_side: Side,
     dice_roll: Option<DiceRoll>,
     last_player: Player,
+    // Zobrist hash of the cells of both sides
+    hash: u64,
 }
 
 impl State {
     pub fn empty() -> Self {
-        Self {
-            max_side: Side::empty(),
-            min_side: Side::empty(),
-            dice_roll: None,
-            last_player: Player::Max,
+        Self::new(Side::empty(), Side::empty(), None, Player::Max)
+    }
+
+    fn new(max_side: Side, min_side: Side, dice_roll: Option<DiceRoll>, last_player: Player) -> Self {
+        let hash = max_side.zobrist_hash(0) ^ min_side.zobrist_hash(3);
+        Self { max_side, min_side, dice_roll, last_player, hash }
+    }
+
+    fn is_over(&self) -> bool {
+        self.min_side.is_full() || self.max_side.is_full()
+    }
+
+    fn side(&self, player: Player) -> &Side {
+        match player {
+            Player::Min => &self.min_side,
+            Player::Max => &self.max_side,
+        }
+    }
+}
+
+/// The rows of the computer's side and of the human's side, then the side that places next and the pending roll or
+/// `-`, e.g. `-/-/- 6/-/- x 2` with a 6 in the human's first row and a 2 for the computer to place. The dice of a row
+/// are written in ascending order, an empty row is `-`.
+impl Display for Fen<State> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = &self.0;
+        for side in [&state.max_side, &state.min_side] {
+            let rows = side.rows.map(|row| match row.0.iter().filter(|&&cell| cell != Cell::Empty).join("") {
+                dice if dice.is_empty() => "-".to_string(),
+                dice => dice,
+            });
+            write!(f, "{} ", rows.join("/"))?;
         }
+        // without a pending roll the player after the last one is next
+        let next = if state.dice_roll.is_some() { state.last_player } else { !state.last_player };
+        write!(f, "{} ", fen::side_symbol(next))?;
+        match state.dice_roll {
+            Some(roll) => write!(f, "{}", roll),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for Fen<State> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [max_side, min_side, next, roll] = fen::fields(s)?;
+        let dice_roll = match roll {
+            "-" => None,
+            _ => Some(roll.parse()?),
+        };
+        let next = fen::parse_side(next)?;
+        let last_player = if dice_roll.is_some() { next } else { !next };
+        Ok(Fen(State::new(parse_side(max_side)?, parse_side(min_side)?, dice_roll, last_player)))
+    }
+}
+
+fn parse_side(s: &str) -> Result<Side, String> {
+    let rows: Vec<_> = s.split('/').map(|row| match row {
+        "-" => Ok(Row::empty()),
+        _ => row.chars().try_fold(Row::empty(), |row_so_far, dice| {
+            row_so_far.add(dice.to_string().parse()?).ok_or_else(|| format!("Row {} has more than 3 dice", row))
+        }),
+    }).collect::<Result<_, String>>()?;
+    let rows = rows.try_into().map_err(|rows: Vec<_>| format!("Expected 3 rows instead of {} in {}", rows.len(), s))?;
+    Ok(Side { rows })
+}
+
+// the pending dice roll is encoded as an extra component after the 18 cells
+const DICE_ROLL_COMPONENT: usize = 18;
+
+impl ZobristHash for State {
+    fn zobrist_hash(&self) -> u64 {
+        let dice_roll = self.dice_roll.map_or(0, |roll| roll.as_i32() as usize);
+        self.hash ^ zobrist::key(DICE_ROLL_COMPONENT, dice_roll) ^ zobrist::player_key(self.last_player)
     }
 }
 
@@ -194,14 +299,37 @@ pub enum Move {
     Place(u8),
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    NoRollPending,
+    RollPending,
+    NotYourRoll,
+    OutOfBounds(u8),
+    RowFull(u8),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is already over"),
+            MoveError::NoRollPending => write!(f, "There is no dice roll to place"),
+            MoveError::RollPending => write!(f, "The last dice roll has not been placed yet"),
+            MoveError::NotYourRoll => write!(f, "The dice roll belongs to the other player"),
+            MoveError::OutOfBounds(row) => write!(f, "Row {} does not exist", row + 1),
+            MoveError::RowFull(row) => write!(f, "Row {} is full", row + 1),
+        }
+    }
+}
+
 pub struct Strategy {
-    stats: NullStats,
+    pub stats: SimpleStats,
 }
 
 impl Strategy {
     pub fn new() -> Self {
         Self {
-            stats: NullStats::default(),
+            stats: SimpleStats::default(),
         }
     }
 }
@@ -209,13 +337,14 @@ impl Strategy {
 impl expecti_min_max::Strategy for Strategy {
     type State = State;
     type Move = Move;
+    type MoveError = MoveError;
     type Cache = NullCache;
-    type Stats = NullStats;
+    type Stats = SimpleStats;
 
     fn possible_moves(state: &State) -> Moves<Move, impl IntoIterator<Item = Move>> {
         match state.dice_roll {
             None => {
-                if state.max_side.is_full() || state.min_side.is_full() {
+                if state.is_over() {
                     Moves::Chance(vec![])
                 } else {
                     use DiceRoll::*;
@@ -231,10 +360,7 @@ impl expecti_min_max::Strategy for Strategy {
                 }
             }
             Some(_) => {
-                let side = match state.last_player {
-                    Player::Min => &state.min_side,
-                    Player::Max => &state.max_side,
-                };
+                let side = state.side(state.last_player);
                 Moves::Player(
                     side.rows
                         .iter()
@@ -249,31 +375,46 @@ impl expecti_min_max::Strategy for Strategy {
     fn do_move(&mut self, state: &State, _move: &Move, player: Player) -> State {
         match _move {
             Move::Roll(roll) => {
-                if state.dice_roll.is_some() {
-                    panic!()
-                }
+                assert!(state.dice_roll.is_none(), "the dice is only rolled after the last roll was placed");
                 State {
                     dice_roll: Some(*roll),
-                    max_side: state.max_side.clone(),
-                    min_side: state.min_side.clone(),
                     last_player: player,
+                    ..*state
                 }
             }
-            Move::Place(row_index) => match state.dice_roll {
-                None => panic!(),
-                Some(roll) => State {
-                    dice_roll: None,
-                    max_side: state.max_side.update(*row_index, |row| match player {
-                        Player::Min => row.remove(roll),
-                        Player::Max => row.add(roll),
-                    }),
-                    min_side: state.min_side.update(*row_index, |row| match player {
-                        Player::Min => row.add(roll),
-                        Player::Max => row.remove(roll),
-                    }),
-                    last_player: player,
-                },
-            },
+            Move::Place(row_index) => {
+                let roll = state.dice_roll.expect("only a pending dice roll can be placed");
+                // only moves from `possible_moves` are played, so the row has space
+                let add = |row: &Row| row.add(roll).expect("the row is not full");
+                let max_side = state.max_side.update(*row_index, |row| match player {
+                    Player::Min => row.remove(roll),
+                    Player::Max => add(row),
+                });
+                let min_side = state.min_side.update(*row_index, |row| match player {
+                    Player::Min => add(row),
+                    Player::Max => row.remove(roll),
+                });
+                // only the placed row changed on both sides
+                let row = usize::from(*row_index);
+                let hash = state.hash
+                    ^ state.max_side.rows[row].zobrist_hash(row) ^ max_side.rows[row].zobrist_hash(row)
+                    ^ state.min_side.rows[row].zobrist_hash(3 + row) ^ min_side.rows[row].zobrist_hash(3 + row);
+                State { max_side, min_side, dice_roll: None, last_player: player, hash }
+            }
+        }
+    }
+
+    fn try_do_move(&mut self, state: &State, _move: &Move, player: Player) -> Result<State, MoveError> {
+        if state.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        match (*_move, state.dice_roll) {
+            (Move::Roll(_), Some(_)) => Err(MoveError::RollPending),
+            (Move::Place(_), None) => Err(MoveError::NoRollPending),
+            (Move::Place(_), Some(_)) if player != state.last_player => Err(MoveError::NotYourRoll),
+            (Move::Place(row), Some(_)) if row >= 3 => Err(MoveError::OutOfBounds(row)),
+            (Move::Place(row), Some(_)) if state.side(player).rows[row as usize].is_full() => Err(MoveError::RowFull(row)),
+            _ => Ok(self.do_move(state, _move, player)),
         }
     }
 
@@ -299,21 +440,66 @@ impl expecti_min_max::Strategy for Strategy {
     }
 }
 
+/// Rolls the scripted dice in order and random ones once the script has run out.
+#[derive(Debug, Clone)]
+pub struct Dice {
+    script: VecDeque<DiceRoll>,
+    rng: SmallRng,
+}
+
+impl Dice {
+    pub fn new(rng: SmallRng) -> Self {
+        Self {
+            script: VecDeque::new(),
+            rng,
+        }
+    }
+
+    /// Rolls `rolls` after the dice scripted so far.
+    pub fn script(&mut self, rolls: impl IntoIterator<Item = DiceRoll>) {
+        self.script.extend(rolls);
+    }
+
+    pub fn roll(&mut self) -> DiceRoll {
+        match self.script.pop_front() {
+            Some(roll) => roll,
+            None => self.rng.sample(StandardUniform),
+        }
+    }
+}
+
+/// The computer searches `depth` moves ahead, dice rolls count as moves.
 pub struct Knucklebones {
     rng: SmallRng,
+    dice: Dice,
     strategy: Strategy,
+    depth: u8,
 }
 
 impl Knucklebones {
-    pub fn new_random() -> Self {
+    /// The dice get their own generator seeded by `rng`, so they don't depend on the choices of the computer.
+    pub fn new(mut rng: SmallRng, depth: u8) -> Self {
+        let dice = Dice::new(SmallRng::from_rng(&mut rng));
         Self {
-            rng: rand::make_rng(),
+            rng,
+            dice,
             strategy: Strategy::new(),
+            depth,
         }
     }
 
+    /// Plays the same game again for the same `seed` and moves of the human.
+    pub fn with_seed(seed: u64, depth: u8) -> Self {
+        Self::new(SmallRng::seed_from_u64(seed), depth)
+    }
+
+    /// Rolls `rolls` before any random dice, e.g. to replay a game from a bug report.
+    pub fn script_dice(&mut self, rolls: impl IntoIterator<Item = DiceRoll>) {
+        self.dice.script(rolls);
+    }
+
     fn roll(&mut self) -> Move {
-        Move::Roll(self.rng.sample(StandardUniform))
+        Move::Roll(self.dice.roll())
     }
 }
 
@@ -328,28 +514,53 @@ impl GameController for Knucklebones {
     }
 
     fn do_move(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
-        let state = self.strategy.do_move(state, &m, Player::Min);
-        let roll = self.roll();
-        Ok(self.strategy.do_move(&state, &roll, Player::Max))
+        self.play(state, m)
     }
 
     fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move) {
-        let moves = alpha_beta_star(&mut self.strategy, state, 15);
+        let moves = alpha_beta_star(&mut self.strategy, state, self.depth);
         let _move = moves.choose(&mut self.rng).unwrap().min_max_move;
-        let state = self.strategy.do_move(&state, &_move, Player::Max);
+        let state = self.strategy.do_move(state, &_move, Player::Max);
         let roll = self.roll();
         (self.strategy.do_move(&state, &roll, Player::Min), _move)
     }
+
+    // the dice are not part of the move, records keep them as chance
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        if let Move::Roll(_) = m {
+            return Err("Only the dice can roll".to_string());
+        }
+        let player = state.last_player;
+        let state = self.strategy.try_do_move(state, &m, player).map_err(|e| e.to_string())?;
+        let roll = self.roll();
+        Ok(self.strategy.do_move(&state, &roll, !player))
+    }
+
+    fn script_chance(&mut self, outcomes: &[&str]) -> Result<(), String> {
+        let rolls = outcomes.iter().map(|roll| roll.parse()).collect::<Result<Vec<DiceRoll>, _>>()?;
+        self.script_dice(rolls);
+        Ok(())
+    }
+
+    fn hint(&mut self, state: &Self::State) -> String {
+        let scored_rows = expecti_min_max::score_possible_moves_for(&mut self.strategy, state, self.depth, Player::Min).into_iter()
+            .filter_map(|m| match m.min_max_move {
+                Move::Place(row) => Some((format!("Row {}", row + 1), m.score)),
+                Move::Roll(_) => None,
+            })
+            .collect::<Vec<_>>();
+        game_controller::format_hint_list(&scored_rows)
+    }
 }
 
 impl Display for Move {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Move::Roll(r) => {
-                write!(f, "Rolled {}", r)
+                write!(f, "r{}", r)
             }
             Move::Place(r) => {
-                write!(f, "Placed at row {}", r + 1)
+                write!(f, "{}", r + 1)
             }
         }
     }
@@ -363,6 +574,8 @@ impl FromStr for Move {
             "1" => Ok(Move::Place(0)),
             "2" => Ok(Move::Place(1)),
             "3" => Ok(Move::Place(2)),
+            // only needed to set up positions, the human can't roll
+            _ if s.starts_with('r') => Ok(Move::Roll(s[1..].parse()?)),
             _ => Err(format!("Invalid move {}, valid moves are 1, 2, 3", s)),
         }
     }
@@ -401,6 +614,10 @@ impl Display for State {
 }
 
 impl game_controller::State for State {
+    fn chance(&self) -> Option<String> {
+        self.dice_roll.map(|roll| roll.to_string())
+    }
+
     fn player(&self) -> game_controller::Player {
         match self.last_player {
             Player::Min => game_controller::Player::Human,
@@ -454,12 +671,12 @@ mod tests {
         assert_eq!(row.0, [Cell::Empty, Cell::Empty, Cell::Empty]);
 
         let row = Row::empty();
-        let row = row.add(DiceRoll::Three);
+        let row = row.add(DiceRoll::Three).unwrap();
         assert_eq!(
             row.0,
             [Cell::Empty, Cell::Empty, Cell::Dice(DiceRoll::Three)]
         );
-        let row = row.add(DiceRoll::One);
+        let row = row.add(DiceRoll::One).unwrap();
         assert_eq!(
             row.0,
             [
@@ -468,7 +685,7 @@ mod tests {
                 Cell::Dice(DiceRoll::Three)
             ]
         );
-        let row = row.add(DiceRoll::Six);
+        let row = row.add(DiceRoll::Six).unwrap();
         assert_eq!(
             row.0,
             [
@@ -484,25 +701,25 @@ mod tests {
         let row = Row::empty();
         assert_eq!(row.score(), 0);
 
-        let row = Row::empty().add(DiceRoll::Six);
+        let row = Row::empty().add(DiceRoll::Six).unwrap();
         assert_eq!(row.score(), 6);
 
         let row = Row::empty()
-            .add(DiceRoll::One)
-            .add(DiceRoll::Two)
-            .add(DiceRoll::Three);
+            .add(DiceRoll::One).unwrap()
+            .add(DiceRoll::Two).unwrap()
+            .add(DiceRoll::Three).unwrap();
         assert_eq!(row.score(), 6);
 
         let row = Row::empty()
-            .add(DiceRoll::Two)
-            .add(DiceRoll::Two)
-            .add(DiceRoll::Three);
+            .add(DiceRoll::Two).unwrap()
+            .add(DiceRoll::Two).unwrap()
+            .add(DiceRoll::Three).unwrap();
         assert_eq!(row.score(), 11);
 
         let row = Row::empty()
-            .add(DiceRoll::Six)
-            .add(DiceRoll::Six)
-            .add(DiceRoll::Six);
+            .add(DiceRoll::Six).unwrap()
+            .add(DiceRoll::Six).unwrap()
+            .add(DiceRoll::Six).unwrap();
         assert_eq!(row.score(), 54);
     }
 
@@ -529,9 +746,7 @@ mod tests {
     #[test]
     fn score() {
         let mut strategy = Strategy::new();
-        let mut state = State::empty();
-        state.min_side = state.min_side.update(0, |r| r.add(DiceRoll::Six).add(DiceRoll::Three));
-        state.max_side = state.max_side.update(2, |r| r.add(DiceRoll::Three));
+        let Fen(state) = "-/-/3 36/-/- o -".parse::<Fen<State>>().unwrap();
 
         assert_eq!(strategy.score(&state, Player::Min), 6);
         assert_eq!(strategy.score(&state, Player::Max), -6);
@@ -539,12 +754,151 @@ mod tests {
 
     #[test]
     fn alpha_beta_second_move() {
-        let mut state = State::empty();
-        state.min_side = state.min_side.update(0, |r| r.add(DiceRoll::Six));
+        let Fen(state) = "-/-/- 6/-/- x 6".parse::<Fen<State>>().unwrap();
         let mut strategy = Strategy::new();
-        state = strategy.do_move(&state, &Move::Roll(DiceRoll::Six), Player::Max);
 
         let result = alpha_beta_star(&mut strategy, &state, 2);
         println!("{:?}", result);
     }
+
+    #[test]
+    fn search_records_stats() {
+        let mut state = State::empty();
+        let mut strategy = Strategy::new();
+        state = strategy.do_move(&state, &Move::Roll(DiceRoll::Four), Player::Max);
+
+        alpha_beta_star(&mut strategy, &state, 5);
+        println!("{}", strategy.stats);
+        assert_eq!(strategy.stats.search_count, 1);
+        assert_eq!(strategy.stats.nodes_per_ply[0], 3);
+        assert!(strategy.stats.state_scored_count > 0);
+        assert!(strategy.stats.prune_count > 0);
+    }
+
+    #[test]
+    fn position_notation() {
+        assert_eq!(Fen(State::empty()).to_string(), "-/-/- -/-/- o -");
+        let mut strategy = Strategy::new();
+        let mut state = State::empty();
+        for (m, player) in [(Move::Roll(DiceRoll::Six), Player::Min), (Move::Place(0), Player::Min), (Move::Roll(DiceRoll::Two), Player::Max)] {
+            state = strategy.do_move(&state, &m, player);
+            assert_eq!(Fen(state).to_string().parse(), Ok(Fen(state)));
+        }
+        assert_eq!(Fen(state).to_string(), "-/-/- 6/-/- x 2");
+
+        let Fen(state) = "126/-/55 6/1/- o -".parse::<Fen<State>>().unwrap();
+        assert_eq!(state.max_side.rows[0].0, [Cell::Dice(DiceRoll::One), Cell::Dice(DiceRoll::Two), Cell::Dice(DiceRoll::Six)]);
+        assert_eq!(state.last_player, Player::Max);
+        assert_eq!(Fen(state).to_string(), "126/-/55 6/1/- o -");
+
+        assert!("1234/-/- -/-/- o -".parse::<Fen<State>>().is_err());
+        assert!("7/-/- -/-/- o -".parse::<Fen<State>>().is_err());
+        assert!("-/- -/-/- o -".parse::<Fen<State>>().is_err());
+    }
+
+    #[test]
+    fn row_add_to_full_row() {
+        let row = Row([Cell::Dice(DiceRoll::One); 3]);
+        assert_eq!(row.add(DiceRoll::Two), None);
+    }
+
+    #[test]
+    fn controller_rolls_scripted_dice() {
+        let mut game = Knucklebones::with_seed(1, 3);
+        game.script_dice([DiceRoll::Six, DiceRoll::Two, DiceRoll::Three]);
+        let state = game.initial();
+        assert_eq!(state.dice_roll, Some(DiceRoll::Six));
+        let state = game.do_move(&state, Move::Place(0)).unwrap();
+        assert_eq!(state.min_side.rows[0], Row([Cell::Empty, Cell::Empty, Cell::Dice(DiceRoll::Six)]));
+        assert_eq!(state.dice_roll, Some(DiceRoll::Two));
+        let (state, _) = game.do_computer_move(&state);
+        assert_eq!(state.dice_roll, Some(DiceRoll::Three));
+    }
+
+    #[test]
+    fn same_seed_plays_same_game() {
+        let play = |seed| {
+            let mut game = Knucklebones::with_seed(seed, 3);
+            let mut state = game.initial();
+            let mut states = vec![state];
+            while game_controller::State::status(&state) == Status::Playing {
+                state = match game_controller::State::player(&state) {
+                    game_controller::Player::Human => {
+                        let row = state.min_side.rows.iter().position(|row| !row.is_full()).unwrap();
+                        game.do_move(&state, Move::Place(row as u8)).unwrap()
+                    }
+                    game_controller::Player::Computer => game.do_computer_move(&state).0,
+                };
+                states.push(state);
+            }
+            states
+        };
+        assert_eq!(play(3), play(3));
+        assert_ne!(play(3), play(4));
+    }
+
+    #[test]
+    fn controller_hint_scores_every_row() {
+        let mut game = Knucklebones::new(SmallRng::seed_from_u64(0), 2);
+        let mut state = State::empty();
+        state.min_side = state.min_side.update(0, |_| Row([Cell::Dice(DiceRoll::One); 3]));
+        let state = Strategy::new().do_move(&state, &Move::Roll(DiceRoll::Six), Player::Min);
+        let hint = game.hint(&state);
+        let rows = hint.lines().map(|line| line.split(':').next().unwrap()).collect::<Vec<_>>();
+        assert_eq!(rows, ["Row 2", "Row 3"]);
+        assert!(hint.contains('*'));
+    }
+
+    #[test]
+    fn controller_rejects_illegal_moves() {
+        let mut game = Knucklebones::with_seed(0, 15);
+        let mut state = State::empty();
+        state.min_side = state.min_side.update(0, |_| Row([Cell::Dice(DiceRoll::One); 3]));
+        assert_eq!(game.do_move(&state, Move::Place(1)), Err("There is no dice roll to place".to_string()));
+
+        let mut strategy = Strategy::new();
+        let state = strategy.do_move(&state, &Move::Roll(DiceRoll::Two), Player::Min);
+        assert_eq!(game.do_move(&state, Move::Place(0)), Err("Row 1 is full".to_string()));
+        assert_eq!(game.do_move(&state, Move::Roll(DiceRoll::Six)), Err("Only the dice can roll".to_string()));
+        assert!(game.do_move(&state, Move::Place(1)).is_ok());
+
+        let mut state = state;
+        state.max_side = Side { rows: [Row([Cell::Dice(DiceRoll::One); 3]); 3] };
+        assert_eq!(game.do_move(&state, Move::Place(1)), Err("The game is already over".to_string()));
+    }
+
+    #[test]
+    fn try_do_move_rejects_illegal_moves() {
+        let mut strategy = Strategy::new();
+        let state = State::empty();
+        assert_eq!(strategy.try_do_move(&state, &Move::Place(0), Player::Min), Err(MoveError::NoRollPending));
+
+        let mut state = strategy.try_do_move(&state, &Move::Roll(DiceRoll::Two), Player::Max).unwrap();
+        assert_eq!(strategy.try_do_move(&state, &Move::Roll(DiceRoll::Six), Player::Max), Err(MoveError::RollPending));
+        assert_eq!(strategy.try_do_move(&state, &Move::Place(3), Player::Max), Err(MoveError::OutOfBounds(3)));
+        state.max_side = state.max_side.update(2, |_| Row([Cell::Dice(DiceRoll::One); 3]));
+        assert_eq!(strategy.try_do_move(&state, &Move::Place(2), Player::Max), Err(MoveError::RowFull(2)));
+        // the roll is placed by the player who rolled it
+        assert_eq!(strategy.try_do_move(&state, &Move::Place(2), Player::Min), Err(MoveError::NotYourRoll));
+        assert!(strategy.try_do_move(&state, &Move::Place(1), Player::Max).is_ok());
+    }
+
+    #[test]
+    fn incremental_zobrist_hash() {
+        let mut strategy = Strategy::new();
+        let mut state = State::empty();
+        let rolls = [DiceRoll::Three, DiceRoll::Three, DiceRoll::Five, DiceRoll::One, DiceRoll::Three, DiceRoll::Five];
+        for (turn, roll) in rolls.into_iter().cycle().take(24).enumerate() {
+            let player = if turn % 2 == 0 { Player::Min } else { Player::Max };
+            state = strategy.do_move(&state, &Move::Roll(roll), player);
+            let Moves::Player(rows) = Strategy::possible_moves(&state) else { panic!("the roll is pending") };
+            let rows = rows.into_iter().collect_vec();
+            state = strategy.do_move(&state, &rows[turn % rows.len()], player);
+            let recomputed = State::new(state.max_side, state.min_side, state.dice_roll, state.last_player);
+            assert_eq!(state.zobrist_hash(), recomputed.zobrist_hash());
+            if state.is_over() {
+                break;
+            }
+        }
+    }
 }
@@ -7,33 +7,36 @@ mod iter_util;
 mod expecti_min_max;
 mod knucklebones;
 mod game_controller;
+mod mcts;
+mod connect_four;
+mod cli;
+mod record;
+mod arena;
+mod protocol;
+mod fen;
 
 extern crate lazy_static;
 
-use crate::game_controller::game_loop;
-use crate::knucklebones::Knucklebones;
-use crate::min_max::{Player, score_possible_moves};
-use crate::min_max::cache::NullCache;
+use std::process::ExitCode;
 
-fn main() {
-    /*use crate::stoplight::CellState::*;
-    let board = stoplight::GameBoard::new([EMPTY, GREEN, EMPTY, EMPTY, EMPTY, EMPTY, GREEN, EMPTY, EMPTY], Player::Min);
-
-    let scored_moves = score_possible_moves(&mut stoplight::Strategy::new(), &stoplight::GameBoard::empty(), u8::MAX);
-    println!("{:?}", scored_moves.iter().max_by_key(|m| m.score).unwrap());*/
-
-   /* let mut strategy = ultimate_ttt::Strategy::new(NullCache::default());
-    let now = std::time::Instant::now();
-    let scored_moves = score_possible_moves(&mut strategy, &ultimate_ttt::GameBoard::empty(), 16);
-    let time = now.elapsed().as_millis();
-    println!("{:?}", scored_moves.iter().max_by_key(|m| m.score).unwrap());
-    println!("Time: {}ms", time);
-    println!("Stats: {:?}", strategy.stats);*/
-    knucklebones();
-}
-
-
-fn knucklebones() {
-    let mut game = Knucklebones::new_random(); 
-    game_loop(&mut game);
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || matches!(args[0].as_str(), "help" | "-h" | "--help") {
+        print!("{}", cli::USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let invocation = match cli::parse_args(args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match cli::run(&invocation) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,264 @@
+use std::time::{Duration, Instant};
+use rand::prelude::*;
+use crate::min_max::{Player, ScoredMove, Strategy};
+use crate::min_max::stats::Stats;
+
+/// How moves are chosen when a game is played out from a newly expanded node.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Playout {
+    Random,
+    /// Plays the move the strategy scores best for the moving player, ties are broken randomly. With probability
+    /// `epsilon` a random move is played instead.
+    Heuristic { epsilon: f64 },
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Config {
+    /// The constant `c` of UCT, larger values explore more.
+    pub exploration: f64,
+    pub playout: Playout,
+    pub budget: Budget,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            exploration: std::f64::consts::SQRT_2,
+            playout: Playout::Random,
+            budget: Budget::Iterations(10_000),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveStatistics<M> {
+    pub min_max_move: M,
+    pub visits: u32,
+    /// Average result of the playouts through this move for the player making it, a draw counts as half a win.
+    pub win_rate: f64,
+}
+
+impl<M> From<MoveStatistics<M>> for ScoredMove<M> {
+    /// The score of a move is its visit count, the most visited move is the most robust choice.
+    fn from(statistics: MoveStatistics<M>) -> Self {
+        ScoredMove::new(statistics.visits as i32, statistics.min_max_move)
+    }
+}
+
+struct Node<S, M> {
+    state: S,
+    // player that made the move leading to this node
+    player: Player,
+    min_max_move: Option<M>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried_moves: Vec<M>,
+    terminal: bool,
+    visits: u32,
+    // sum of the playout results from the perspective of `player`
+    reward: f64,
+}
+
+fn reward_for(winner: Option<Player>, player: Player) -> f64 {
+    match winner {
+        Some(winner) if winner == player => 1.0,
+        Some(_) => 0.0,
+        None => 0.5,
+    }
+}
+
+/// Same shape as [crate::min_max::score_possible_moves], the score of a move is its visit count.
+pub fn score_possible_moves<STRATEGY, R>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    config: &Config,
+    rng: &mut R,
+) -> Vec<ScoredMove<STRATEGY::Move>>
+where
+    STRATEGY: Strategy,
+    STRATEGY::State: Clone,
+    R: Rng + ?Sized,
+{
+    search(strategy, state, config, rng).into_iter().map(ScoredMove::from).collect()
+}
+
+/// Runs Monte Carlo Tree Search with UCT selection from `state` where [Player::Max] is to move and returns the
+/// statistics of every root move. A game is over once [Strategy::possible_moves] has no moves left, the sign of
+/// [Strategy::score] then tells who won.
+pub fn search<STRATEGY, R>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    config: &Config,
+    rng: &mut R,
+) -> Vec<MoveStatistics<STRATEGY::Move>>
+where
+    STRATEGY: Strategy,
+    STRATEGY::State: Clone,
+    R: Rng + ?Sized,
+{
+    search_for(strategy, state, Player::Max, config, rng)
+}
+
+/// Like [search] but for `player` to move instead of [Player::Max].
+pub fn search_for<STRATEGY, R>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    config: &Config,
+    rng: &mut R,
+) -> Vec<MoveStatistics<STRATEGY::Move>>
+where
+    STRATEGY: Strategy,
+    STRATEGY::State: Clone,
+    R: Rng + ?Sized,
+{
+    let start = Instant::now();
+    // the root is reached by a move of the other player
+    let mut tree = vec![new_node::<STRATEGY>(state.clone(), !player, None, None)];
+
+    let mut iterations = 0;
+    while !budget_exhausted(config.budget, iterations, start) {
+        iterations += 1;
+        let leaf = select(&tree, config.exploration);
+        let node = expand(strategy, &mut tree, leaf, rng);
+        let winner = playout(strategy, &tree[node], config.playout, rng);
+        backpropagate(&mut tree, node, winner);
+    }
+    strategy.stats().record_search_time(start.elapsed());
+
+    let children = std::mem::take(&mut tree[0].children);
+    children.into_iter().map(|child| {
+        let node = &mut tree[child];
+        let win_rate = if node.visits == 0 { 0.5 } else { node.reward / f64::from(node.visits) };
+        MoveStatistics {
+            min_max_move: node.min_max_move.take().expect("only the root has no move"),
+            visits: node.visits,
+            win_rate,
+        }
+    }).collect()
+}
+
+fn budget_exhausted(budget: Budget, iterations: u32, start: Instant) -> bool {
+    match budget {
+        Budget::Iterations(max) => iterations >= max,
+        Budget::Time(time) => start.elapsed() >= time,
+    }
+}
+
+fn new_node<STRATEGY>(state: STRATEGY::State, player: Player, min_max_move: Option<STRATEGY::Move>, parent: Option<usize>) -> Node<STRATEGY::State, STRATEGY::Move>
+where
+    STRATEGY: Strategy,
+{
+    // the game is over once there are no moves left
+    let untried_moves: Vec<_> = STRATEGY::possible_moves(&state).into_iter().collect();
+    Node {
+        terminal: untried_moves.is_empty(),
+        state,
+        player,
+        min_max_move,
+        parent,
+        children: vec![],
+        untried_moves,
+        visits: 0,
+        reward: 0.0,
+    }
+}
+
+// follows the child with the best UCT value until a node that is not fully expanded is reached
+fn select<S, M>(tree: &[Node<S, M>], exploration: f64) -> usize {
+    let mut current = 0;
+    loop {
+        let node = &tree[current];
+        if node.terminal || !node.untried_moves.is_empty() {
+            return current;
+        }
+        let log_visits = f64::from(node.visits).ln();
+        current = *node.children.iter().max_by(|&&a, &&b| {
+            uct(&tree[a], log_visits, exploration).total_cmp(&uct(&tree[b], log_visits, exploration))
+        }).expect("non terminal nodes without untried moves have children");
+    }
+}
+
+fn uct<S, M>(node: &Node<S, M>, parent_log_visits: f64, exploration: f64) -> f64 {
+    let visits = f64::from(node.visits);
+    node.reward / visits + exploration * (parent_log_visits / visits).sqrt()
+}
+
+fn expand<STRATEGY, R>(strategy: &mut STRATEGY, tree: &mut Vec<Node<STRATEGY::State, STRATEGY::Move>>, leaf: usize, rng: &mut R) -> usize
+where
+    STRATEGY: Strategy,
+    STRATEGY::State: Clone,
+    R: Rng + ?Sized,
+{
+    let node = &mut tree[leaf];
+    if node.terminal {
+        return leaf;
+    }
+    let index = rng.random_range(0..node.untried_moves.len());
+    let m = node.untried_moves.swap_remove(index);
+    let player = !node.player;
+    let next_state = strategy.do_move(&node.state, &m, player);
+    let child = new_node::<STRATEGY>(next_state, player, Some(m), Some(leaf));
+    tree.push(child);
+    let child_index = tree.len() - 1;
+    tree[leaf].children.push(child_index);
+    child_index
+}
+
+// plays until the game is over and returns the winner
+fn playout<STRATEGY, R>(strategy: &mut STRATEGY, node: &Node<STRATEGY::State, STRATEGY::Move>, mode: Playout, rng: &mut R) -> Option<Player>
+where
+    STRATEGY: Strategy,
+    STRATEGY::State: Clone,
+    R: Rng + ?Sized,
+{
+    let mut state = node.state.clone();
+    let mut player = !node.player;
+    loop {
+        let mut moves: Vec<_> = STRATEGY::possible_moves(&state).into_iter().collect();
+        if moves.is_empty() {
+            strategy.stats().record_state_scored();
+            let score = strategy.score(&state, player);
+            return match score.signum() {
+                1 => Some(player),
+                -1 => Some(!player),
+                _ => None,
+            };
+        }
+        state = match mode {
+            Playout::Heuristic { epsilon } if !rng.random_bool(epsilon) => {
+                let scored = moves.into_iter().map(|m| {
+                    let next_state = strategy.do_move(&state, &m, player);
+                    let score = strategy.score(&next_state, player);
+                    (score, next_state)
+                }).collect::<Vec<_>>();
+                let best = scored.iter().map(|(score, _)| *score).max().expect("moves is not empty");
+                let best_states = scored.into_iter().filter(|(score, _)| *score == best).collect::<Vec<_>>();
+                let index = rng.random_range(0..best_states.len());
+                best_states.into_iter().nth(index).expect("index is in range").1
+            }
+            _ => {
+                let index = rng.random_range(0..moves.len());
+                let m = moves.swap_remove(index);
+                strategy.do_move(&state, &m, player)
+            }
+        };
+        player = !player;
+    }
+}
+
+fn backpropagate<S, M>(tree: &mut [Node<S, M>], node: usize, winner: Option<Player>) {
+    let mut current = Some(node);
+    while let Some(index) = current {
+        let node = &mut tree[index];
+        node.visits += 1;
+        node.reward += reward_for(winner, node.player);
+        current = node.parent;
+    }
+}
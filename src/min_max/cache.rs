@@ -1,4 +1,6 @@
-use ahash::{HashMap};
+use ahash::HashMap;
+use crate::min_max::symmetry::CanonicalState;
+use crate::min_max::zobrist::ZobristHash;
 
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
 pub enum CacheFlag {
@@ -12,6 +14,8 @@ pub struct CacheEntry {
     pub(super) value: i32,
     pub(super) level: u8,
     pub(super) flag: CacheFlag,
+    // position of the best move in the order produced by `possible_moves`
+    pub(super) best_move: Option<u8>,
 }
 
 pub trait Cache<S> {
@@ -26,6 +30,14 @@ impl <S> HashMapCache<S> {
     pub fn new(map: HashMap<S, CacheEntry>) -> Self {
         Self(map)
     }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 impl <S> Default for HashMapCache<S> {
@@ -53,4 +65,266 @@ impl<S> Cache<S> for NullCache {
     fn get(&mut self, _state: &S) -> Option<CacheEntry> {
         None
     }
-}
\ No newline at end of file
+}
+
+/// Wraps a cache so that all states that are equivalent under symmetry share one entry. States are replaced by their
+/// [CanonicalState] before they are passed on to the inner cache. The best move of an entry is the position of the
+/// move in the order of `possible_moves`, which differs between symmetric states, so it is only kept for states that
+/// are canonical themselves.
+#[derive(Debug, Clone)]
+pub struct SymmetricCache<C> {
+    inner: C,
+    hit_count: u64,
+    symmetric_hit_count: u64,
+}
+
+impl<C> SymmetricCache<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner, hit_count: 0, symmetric_hit_count: 0 }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn hit_count(&self) -> u64 {
+        self.hit_count
+    }
+
+    /// Hits of states that are not canonical, i.e. that share the entry of a different, but symmetric, state.
+    pub fn symmetric_hit_count(&self) -> u64 {
+        self.symmetric_hit_count
+    }
+}
+
+impl<C: Default> Default for SymmetricCache<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<S, C> Cache<S> for SymmetricCache<C> where S: CanonicalState + Eq, C: Cache<S> {
+    fn set(&mut self, state: &S, entry: CacheEntry) {
+        let canonical = state.canonical_state();
+        let best_move = entry.best_move.filter(|_| *state == canonical);
+        self.inner.set(&canonical, CacheEntry { best_move, ..entry });
+    }
+
+    fn get(&mut self, state: &S) -> Option<CacheEntry> {
+        let canonical = state.canonical_state();
+        let entry = self.inner.get(&canonical)?;
+        self.hit_count += 1;
+        if *state == canonical {
+            Some(entry)
+        } else {
+            self.symmetric_hit_count += 1;
+            Some(CacheEntry { best_move: None, ..entry })
+        }
+    }
+}
+
+/// Decides whether a new entry may overwrite the entry already stored in its slot.
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+pub enum ReplacementPolicy {
+    /// Keep the entry that was searched deeper, ties go to the new entry.
+    DepthPreferred,
+    AlwaysReplace,
+    /// Every index holds two slots, a depth preferred one and one that is always replaced.
+    TwoTier,
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    key: u64,
+    entry: CacheEntry,
+}
+
+/// A fixed size transposition table keyed by the Zobrist hash of the state. Unlike [HashMapCache] it never grows and
+/// does not store the states themselves, entries are overwritten according to the [ReplacementPolicy].
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    slots: Vec<Option<Slot>>,
+    policy: ReplacementPolicy,
+}
+
+impl TranspositionTable {
+    pub fn new(slot_count: usize, policy: ReplacementPolicy) -> Self {
+        // two tier needs pairs of slots
+        let slot_count = slot_count.max(2) & !1;
+        Self { slots: vec![None; slot_count], policy }
+    }
+
+    pub fn with_capacity_mb(megabytes: usize, policy: ReplacementPolicy) -> Self {
+        Self::new(megabytes * 1024 * 1024 / std::mem::size_of::<Option<Slot>>(), policy)
+    }
+
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn occupied_slots(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_some()).count()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    // the slots the entry for `key` may be stored in
+    fn candidates(&self, key: u64) -> std::ops::Range<usize> {
+        match self.policy {
+            ReplacementPolicy::DepthPreferred | ReplacementPolicy::AlwaysReplace => {
+                let index = (key % self.slots.len() as u64) as usize;
+                index..index + 1
+            }
+            ReplacementPolicy::TwoTier => {
+                let index = (key % (self.slots.len() / 2) as u64) as usize * 2;
+                index..index + 2
+            }
+        }
+    }
+}
+
+impl<S: ZobristHash> Cache<S> for TranspositionTable {
+    fn set(&mut self, state: &S, entry: CacheEntry) {
+        let key = state.zobrist_hash();
+        let candidates = self.candidates(key);
+        let first = candidates.start;
+        let replace = |stored: &Option<Slot>, depth_preferred: bool| match stored {
+            None => true,
+            Some(slot) => slot.key == key || !depth_preferred || entry.level >= slot.entry.level,
+        };
+        let index = match self.policy {
+            ReplacementPolicy::AlwaysReplace => first,
+            ReplacementPolicy::DepthPreferred => {
+                if !replace(&self.slots[first], true) {
+                    return;
+                }
+                first
+            }
+            ReplacementPolicy::TwoTier => {
+                if self.slots[first + 1].as_ref().is_some_and(|slot| slot.key == key) {
+                    // do not keep two entries for the same state
+                    self.slots[first + 1] = None;
+                }
+                if !replace(&self.slots[first], true) {
+                    first + 1
+                } else {
+                    // the replaced entry moves to the always replace slot
+                    if self.slots[first].as_ref().is_some_and(|slot| slot.key != key) {
+                        self.slots[first + 1] = self.slots[first].take();
+                    }
+                    first
+                }
+            }
+        };
+        self.slots[index] = Some(Slot { key, entry });
+    }
+
+    fn get(&mut self, state: &S) -> Option<CacheEntry> {
+        let key = state.zobrist_hash();
+        self.slots[self.candidates(key)].iter()
+            .flatten()
+            .find(|slot| slot.key == key)
+            .map(|slot| slot.entry.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::min_max::cache::{Cache, CacheEntry, CacheFlag, HashMapCache, ReplacementPolicy, SymmetricCache, TranspositionTable};
+    use crate::min_max::symmetry::CanonicalState;
+    use crate::min_max::zobrist::ZobristHash;
+
+    struct Hashed(u64);
+
+    impl ZobristHash for Hashed {
+        fn zobrist_hash(&self) -> u64 {
+            self.0
+        }
+    }
+
+    fn entry(level: u8) -> CacheEntry {
+        CacheEntry { value: i32::from(level), level, flag: CacheFlag::Exact, best_move: None }
+    }
+
+    // a number that is symmetric to its negation
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+    struct Mirrored(i32);
+
+    impl CanonicalState for Mirrored {
+        fn canonical_state(&self) -> Self {
+            Mirrored(self.0.abs())
+        }
+    }
+
+    #[test]
+    fn symmetric_cache_keeps_best_move_of_canonical_states() {
+        let mut cache = SymmetricCache::new(HashMapCache::default());
+        cache.set(&Mirrored(1), CacheEntry { best_move: Some(2), ..entry(3) });
+        assert_eq!(cache.get(&Mirrored(1)), Some(CacheEntry { best_move: Some(2), ..entry(3) }));
+        assert_eq!(cache.get(&Mirrored(-1)), Some(entry(3)));
+        assert_eq!((cache.hit_count(), cache.symmetric_hit_count()), (2, 1));
+
+        // the move order of the mirrored state means nothing for the canonical one
+        cache.set(&Mirrored(-2), CacheEntry { best_move: Some(1), ..entry(4) });
+        assert_eq!(cache.get(&Mirrored(2)), Some(entry(4)));
+        assert_eq!(cache.get(&Mirrored(3)), None);
+        assert_eq!(cache.inner().len(), 2);
+    }
+
+    #[test]
+    fn transposition_table_depth_preferred() {
+        let mut table = TranspositionTable::new(2, ReplacementPolicy::DepthPreferred);
+        table.set(&Hashed(0), entry(5));
+        // same slot, shallower
+        table.set(&Hashed(2), entry(3));
+        assert_eq!(table.get(&Hashed(0)), Some(entry(5)));
+        assert_eq!(table.get(&Hashed(2)), None);
+
+        // same state is always updated
+        table.set(&Hashed(0), entry(1));
+        assert_eq!(table.get(&Hashed(0)), Some(entry(1)));
+
+        table.set(&Hashed(1), entry(1));
+        assert_eq!(table.get(&Hashed(1)), Some(entry(1)));
+        assert_eq!(table.occupied_slots(), 2);
+    }
+
+    #[test]
+    fn transposition_table_always_replace() {
+        let mut table = TranspositionTable::new(2, ReplacementPolicy::AlwaysReplace);
+        table.set(&Hashed(0), entry(5));
+        table.set(&Hashed(2), entry(3));
+        assert_eq!(table.get(&Hashed(0)), None);
+        assert_eq!(table.get(&Hashed(2)), Some(entry(3)));
+    }
+
+    #[test]
+    fn transposition_table_two_tier() {
+        let mut table = TranspositionTable::new(4, ReplacementPolicy::TwoTier);
+        table.set(&Hashed(0), entry(5));
+        table.set(&Hashed(2), entry(3));
+        assert_eq!(table.get(&Hashed(0)), Some(entry(5)));
+        assert_eq!(table.get(&Hashed(2)), Some(entry(3)));
+
+        // the deeper entry keeps its slot, the always replace slot is overwritten
+        table.set(&Hashed(4), entry(1));
+        assert_eq!(table.get(&Hashed(0)), Some(entry(5)));
+        assert_eq!(table.get(&Hashed(2)), None);
+        assert_eq!(table.get(&Hashed(4)), Some(entry(1)));
+
+        // a deeper entry demotes the depth preferred one
+        table.set(&Hashed(6), entry(7));
+        assert_eq!(table.get(&Hashed(6)), Some(entry(7)));
+        assert_eq!(table.get(&Hashed(0)), Some(entry(5)));
+        assert_eq!(table.get(&Hashed(4)), None);
+    }
+
+    #[test]
+    fn transposition_table_capacity() {
+        let table = TranspositionTable::with_capacity_mb(1, ReplacementPolicy::TwoTier);
+        assert!(table.slot_count() > 10_000);
+        assert_eq!(table.slot_count() % 2, 0);
+    }
+}
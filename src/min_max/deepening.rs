@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+use crate::min_max::{score_moves, Player, ScoredLine, ScoredMove, SearchBudget, SearchConfig, SearchControl, Strategy};
+
+#[derive(Debug, Clone)]
+pub struct DeepeningResult<M> {
+    /// The scored root moves of the deepest search that completed within the budget.
+    pub scored_moves: Vec<ScoredMove<M>>,
+    /// The `max_level` the scored moves were computed with.
+    pub depth: u8,
+    /// The replies expected after the best move, only collected by [iterative_deepening_with].
+    pub principal_variation: Vec<M>,
+    /// Nodes visited over all iterations, including the aborted one.
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+/// Searches with `max_level` 1, 2, 3, ... until the budget runs out or `max_level` is reached and returns the result
+/// of the last iteration that completed. The cache of the strategy is kept between iterations and the root moves are
+/// searched best first according to the previous iteration.
+///
+/// Returns `None` if not even the first iteration finished within the budget.
+pub fn iterative_deepening<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    budget: SearchBudget,
+    max_level: u8,
+) -> Option<DeepeningResult<STRATEGY::Move>> where STRATEGY::Move: Clone {
+    iterative_deepening_for(strategy, state, Player::Max, budget, max_level, SearchConfig::default())
+}
+
+/// Like [iterative_deepening] but for `player` to move instead of [Player::Max] and with the search algorithm chosen
+/// by `config`.
+pub fn iterative_deepening_for<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    budget: SearchBudget,
+    max_level: u8,
+    config: SearchConfig,
+) -> Option<DeepeningResult<STRATEGY::Move>> where STRATEGY::Move: Clone {
+    let moves = STRATEGY::possible_moves(state).into_iter().collect();
+    let score = |strategy: &mut STRATEGY, moves, depth, control: &mut SearchControl| score_moves(strategy, state, player, moves, depth, false, control);
+    deepen(strategy, moves, budget, max_level, None, config, score, |_, _| {})
+}
+
+/// Like [iterative_deepening] but for `player` to move and with the principal variation of the best move. Every
+/// completed iteration is passed to `on_iteration` together with the strategy, and the search is aborted once `stop`
+/// is set, e.g. by another thread.
+pub fn iterative_deepening_with<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    budget: SearchBudget,
+    max_level: u8,
+    stop: Arc<AtomicBool>,
+    on_iteration: impl FnMut(&mut STRATEGY, &DeepeningResult<STRATEGY::Move>),
+) -> Option<DeepeningResult<STRATEGY::Move>> where STRATEGY::Move: Clone {
+    let moves = STRATEGY::possible_moves(state).into_iter().collect();
+    let score = |strategy: &mut STRATEGY, moves, depth, control: &mut SearchControl| score_moves(strategy, state, player, moves, depth, true, control);
+    deepen(strategy, moves, budget, max_level, Some(stop), SearchConfig::default(), score, on_iteration)
+}
+
+/// The loop of the iterative deepening of both the searches with and without chance. `score` scores the root `moves` in
+/// the given order with a `max_level`, the moves of every completed iteration are searched best first by the next.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn deepen<S, M: Clone>(
+    strategy: &mut S,
+    mut moves: Vec<M>,
+    budget: SearchBudget,
+    max_level: u8,
+    stop: Option<Arc<AtomicBool>>,
+    config: SearchConfig,
+    mut score: impl FnMut(&mut S, Vec<M>, u8, &mut SearchControl) -> Vec<ScoredLine<M>>,
+    mut on_iteration: impl FnMut(&mut S, &DeepeningResult<M>),
+) -> Option<DeepeningResult<M>> {
+    let start = Instant::now();
+    let mut control = SearchControl::new(budget, start);
+    control.stop = stop;
+    control.algorithm = config.algorithm;
+    let mut result = None;
+
+    for depth in 1..=max_level {
+        control.reached_horizon = false;
+        let mut lines = score(strategy, moves, depth, &mut control);
+        if control.aborted {
+            break;
+        }
+        // stable sort keeps the previous order among equally scored moves
+        lines.sort_by_key(|line| -line.score);
+        let principal_variation = lines.first().map(|line| line.principal_variation.clone()).unwrap_or_default();
+        let scored_moves: Vec<_> = lines.into_iter().map(ScoredMove::from).collect();
+        let reached_horizon = control.reached_horizon;
+        moves = scored_moves.iter().map(|m| m.min_max_move.clone()).collect();
+        let iteration = DeepeningResult { scored_moves, depth, principal_variation, nodes: control.nodes, elapsed: start.elapsed() };
+        on_iteration(strategy, &iteration);
+        result = Some(iteration);
+        if !reached_horizon {
+            // the whole game tree was searched, deeper iterations can not change the outcome
+            break;
+        }
+    }
+
+    result.map(|result| DeepeningResult { nodes: control.nodes, elapsed: start.elapsed(), ..result })
+}
@@ -1,11 +1,16 @@
 pub mod symmetry;
 pub mod cache;
 pub mod stats;
+pub mod deepening;
+pub mod zobrist;
 
 use itertools::Itertools;
 use std::fmt::{Debug, Display};
 use std::hash::{Hash};
 use std::ops::Not;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 pub use crate::min_max::cache::{CacheEntry, CacheFlag};
 use crate::min_max::cache::Cache;
 use crate::min_max::stats::Stats;
@@ -31,6 +36,36 @@ impl<M> ScoredMove<M> {
     }
 }
 
+/// A root move whose score is only exact if `flag` is [CacheFlag::Exact]. For [CacheFlag::UpperBound] the real score
+/// is at most `score`.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct BoundedScoredMove<M> {
+    pub score: i32,
+    pub flag: CacheFlag,
+    pub min_max_move: M,
+}
+
+impl<M> From<BoundedScoredMove<M>> for ScoredMove<M> {
+    fn from(bounded: BoundedScoredMove<M>) -> Self {
+        ScoredMove::new(bounded.score, bounded.min_max_move)
+    }
+}
+
+/// A scored root move together with the principal variation, i.e. the sequence of replies both players are expected
+/// to make after `min_max_move`. The variation ends early where the search was answered by the cache.
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+pub struct ScoredLine<M> {
+    pub score: i32,
+    pub min_max_move: M,
+    pub principal_variation: Vec<M>,
+}
+
+impl<M> From<ScoredLine<M>> for ScoredMove<M> {
+    fn from(line: ScoredLine<M>) -> Self {
+        ScoredMove::new(line.score, line.min_max_move)
+    }
+}
+
 impl<I, S: Symmetry<I>> ScoredMove<SymmetricMove<I, S>> {
     pub fn expand(&self) -> Vec<ScoredMove<I>> {
         let score = self.score;
@@ -54,11 +89,19 @@ impl Not for Player {
 pub trait Strategy {
     type State;
     type Move;
+    /// Why [Strategy::try_do_move] rejected a move.
+    type MoveError: Display;
     type Cache: Cache<Self::State>;
     type Stats: Stats;
 
+    /// The moves of the player to move, none once the game is over. The search scores states without moves as
+    /// leaves, so a game that is decided must not offer moves, otherwise the search plays on past the win and both
+    /// players can complete a line.
     fn possible_moves(state: &Self::State) -> impl IntoIterator<Item=Self::Move>;
+    /// Plays a move of [Strategy::possible_moves], may panic for any other move.
     fn do_move(&mut self, state: &Self::State, _move: &Self::Move, player: Player) -> Self::State;
+    /// Plays any move, e.g. one entered by a human, if it is legal in `state`.
+    fn try_do_move(&mut self, state: &Self::State, _move: &Self::Move, player: Player) -> Result<Self::State, Self::MoveError>;
     
     fn score(&mut self, state: &Self::State, player: Player) -> i32;
 
@@ -66,27 +109,274 @@ pub trait Strategy {
     fn stats(&mut self) -> &mut Self::Stats;
 }
 
+/// Upper limits for a single search. `None` means unlimited.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SearchBudget {
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+}
+
+impl SearchBudget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn time(time: Duration) -> Self {
+        Self { time: Some(time), nodes: None }
+    }
+
+    pub fn nodes(nodes: u64) -> Self {
+        Self { time: None, nodes: Some(nodes) }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub enum Algorithm {
+    /// Plain fail-soft negamax with alpha-beta pruning.
+    #[default]
+    AlphaBeta,
+    /// Principal Variation Search (NegaScout): every move after the first is searched with a null window first and
+    /// only searched again with the full window if it turns out to be better.
+    PrincipalVariation,
+    /// Every root move is scored by a series of null window searches converging on the score, see [mtdf]. Relies on
+    /// the cache to not repeat work between the searches, principal variations are not collected.
+    Mtdf,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SearchConfig {
+    pub algorithm: Algorithm,
+}
+
+impl SearchConfig {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self { algorithm }
+    }
+}
+
+// Checking the clock is comparatively expensive, so the deadline is only looked at every few nodes.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// Tracks the budget of a running search. Once `aborted` is set all values returned by the search are meaningless
+/// and must not be stored in the cache.
+#[derive(Debug)]
+pub(crate) struct SearchControl {
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    pub(crate) nodes: u64,
+    // `max_level` of the running iteration, used to derive the ply of a node from its remaining levels
+    root_level: u8,
+    algorithm: Algorithm,
+    pub(crate) aborted: bool,
+    // set when at least one node was cut off by the depth limit rather than by the end of the game
+    pub(crate) reached_horizon: bool,
+    // aborts the search once set, it is checked as rarely as the deadline
+    pub(crate) stop: Option<Arc<AtomicBool>>,
+}
+
+impl SearchControl {
+    pub(crate) fn new(budget: SearchBudget, start: Instant) -> Self {
+        Self {
+            deadline: budget.time.map(|time| start + time),
+            node_limit: budget.nodes,
+            nodes: 0,
+            root_level: 0,
+            algorithm: Algorithm::default(),
+            aborted: false,
+            reached_horizon: false,
+            stop: None,
+        }
+    }
+
+    pub(crate) fn unlimited() -> Self {
+        Self::new(SearchBudget::unlimited(), Instant::now())
+    }
+
+    fn with_config(config: SearchConfig) -> Self {
+        Self { algorithm: config.algorithm, ..Self::unlimited() }
+    }
+
+    pub(crate) fn enter_node(&mut self) -> bool {
+        if self.aborted {
+            return false;
+        }
+        self.nodes += 1;
+        if self.node_limit.is_some_and(|limit| self.nodes > limit) {
+            self.aborted = true;
+        }
+        if self.nodes.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+            if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                self.aborted = true;
+            }
+            if self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                self.aborted = true;
+            }
+        }
+        !self.aborted
+    }
+}
+
+/// Returns all moves with the best score. Uses [score_best_moves], so it is cheaper than scoring all moves.
 pub fn alpha_beta<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &mut STRATEGY::State, max_level: u8) -> Vec<ScoredMove<STRATEGY::Move>> {
-    score_possible_moves(strategy, state, max_level).into_iter().max_set_by_key(|state| state.score)
+    score_best_moves(strategy, state, max_level).into_iter()
+        .filter(|m| m.flag == CacheFlag::Exact)
+        .max_set_by_key(|m| m.score)
+        .into_iter()
+        .map(ScoredMove::from)
+        .collect()
+}
+
+/// Scores the root moves with a window that narrows to the best score found so far. Moves scoring at least as well as
+/// all earlier moves get an exact score, every other move only an upper bound. Use [score_possible_moves] if all
+/// scores have to be exact.
+pub fn score_best_moves<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8) -> Vec<BoundedScoredMove<STRATEGY::Move>> {
+    let start = Instant::now();
+    let mut control = SearchControl::unlimited();
+    control.root_level = max_level;
+    let mut best_score = -i32::MAX;
+    let scored_moves = STRATEGY::possible_moves(state).into_iter().map(|m| {
+        let next_state = strategy.do_move(state, &m, Player::Max);
+        // one below the best score, so moves that tie with the best one are still scored exactly
+        let alpha = best_score.saturating_sub(1).max(-i32::MAX);
+        let score = -alpha_beta_eval_single_move(strategy, &next_state, Player::Min, max_level.saturating_sub(1), -i32::MAX, -alpha, None, &mut control);
+        best_score = best_score.max(score);
+        let flag = if score <= alpha { CacheFlag::UpperBound } else { CacheFlag::Exact };
+        BoundedScoredMove { score, flag, min_max_move: m }
+    }).collect();
+    strategy.stats().record_search_time(start.elapsed());
+    scored_moves
 }
 
 pub fn score_possible_moves<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8) -> Vec<ScoredMove<STRATEGY::Move>> {
     let pos_moves = STRATEGY::possible_moves(&state);
-    return pos_moves.into_iter().map(|m| {
-        let next_state = strategy.do_move(state, &m, Player::Max);
-        let score = -alpha_beta_eval_single_move(strategy, &next_state, Player::Min, max_level - 1, -i32::MAX, i32::MAX);
-        ScoredMove::new(score, m)
+    score_moves(strategy, state, Player::Max, pos_moves, max_level, false, &mut SearchControl::unlimited())
+        .into_iter()
+        .map(ScoredMove::from)
+        .collect()
+}
+
+/// Like [score_possible_moves] but for `player` to move instead of [Player::Max], e.g. to give the human a hint.
+pub fn score_possible_moves_for<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8, player: Player) -> Vec<ScoredMove<STRATEGY::Move>> {
+    let pos_moves = STRATEGY::possible_moves(state);
+    score_moves(strategy, state, player, pos_moves, max_level, false, &mut SearchControl::unlimited())
+        .into_iter()
+        .map(ScoredMove::from)
+        .collect()
+}
+
+/// Like [score_possible_moves] but with the search algorithm chosen by `config`. All algorithms agree on the scores.
+pub fn score_possible_moves_with<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8, config: SearchConfig) -> Vec<ScoredMove<STRATEGY::Move>> {
+    let pos_moves = STRATEGY::possible_moves(state);
+    score_moves(strategy, state, Player::Max, pos_moves, max_level, false, &mut SearchControl::with_config(config))
+        .into_iter()
+        .map(ScoredMove::from)
+        .collect()
+}
+
+/// MTD(f): finds the score of `state` for [Player::Max] with null window searches only, starting at `first_guess`.
+/// Every search tells whether the score is above or below its window and the bounds stored in the cache make the
+/// repeated searches cheap. The closer the guess, the fewer searches are needed.
+pub fn mtdf<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8, first_guess: i32) -> i32 {
+    let start = Instant::now();
+    let mut control = SearchControl::unlimited();
+    control.root_level = max_level;
+    let score = mtdf_eval(strategy, state, Player::Max, max_level, first_guess, &mut control);
+    strategy.stats().record_search_time(start.elapsed());
+    score
+}
+
+fn mtdf_eval<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, player: Player, remaining_levels: u8, first_guess: i32, control: &mut SearchControl) -> i32 {
+    let mut score = first_guess.clamp(-i32::MAX + 1, i32::MAX - 1);
+    let mut lower_bound = -i32::MAX;
+    let mut upper_bound = i32::MAX;
+    while lower_bound < upper_bound {
+        let beta = if score == lower_bound { score + 1 } else { score };
+        score = alpha_beta_eval_single_move(strategy, state, player, remaining_levels, beta - 1, beta, None, control);
+        if control.aborted {
+            return 0;
+        }
+        if score < beta {
+            upper_bound = score;
+        } else {
+            lower_bound = score;
+        }
+    }
+    score
+}
+
+/// Like [score_possible_moves] but additionally returns the principal variation of every root move.
+pub fn score_possible_lines<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, max_level: u8) -> Vec<ScoredLine<STRATEGY::Move>> {
+    let pos_moves = STRATEGY::possible_moves(state);
+    score_moves(strategy, state, Player::Max, pos_moves, max_level, true, &mut SearchControl::unlimited())
+}
+
+// Scores the given root moves of `player` in order. If the search is aborted the returned scores are meaningless.
+#[allow(clippy::too_many_arguments)]
+fn score_moves<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    moves: impl IntoIterator<Item=STRATEGY::Move>,
+    max_level: u8,
+    collect_pv: bool,
+    control: &mut SearchControl,
+) -> Vec<ScoredLine<STRATEGY::Move>> {
+    let start = Instant::now();
+    control.root_level = max_level;
+    // sibling root moves tend to have similar scores, so the previous score is the guess for MTD(f)
+    let mut guess = 0;
+    let lines = moves.into_iter().map(|m| {
+        let next_state = strategy.do_move(state, &m, player);
+        let mut pv = Vec::new();
+        let pv_out = if collect_pv { Some(&mut pv) } else { None };
+        let score = match control.algorithm {
+            Algorithm::Mtdf => -mtdf_eval(strategy, &next_state, !player, max_level.saturating_sub(1), -guess, control),
+            Algorithm::AlphaBeta | Algorithm::PrincipalVariation => {
+                -alpha_beta_eval_single_move(strategy, &next_state, !player, max_level.saturating_sub(1), -i32::MAX, i32::MAX, pv_out, control)
+            }
+        };
+        guess = score;
+        ScoredLine { score, min_max_move: m, principal_variation: pv }
     }).collect();
+    strategy.stats().record_search_time(start.elapsed());
+    lines
 }
 
-fn alpha_beta_eval_single_move<STRATEGY: Strategy>(strategy: &mut STRATEGY, state: &STRATEGY::State, player: Player, remaining_levels: u8, mut alpha: i32, mut beta: i32) -> i32 {
+// If `pv` is given it receives the best line found from `state` on.
+#[allow(clippy::too_many_arguments)]
+fn alpha_beta_eval_single_move<STRATEGY: Strategy>(
+    strategy: &mut STRATEGY,
+    state: &STRATEGY::State,
+    player: Player,
+    remaining_levels: u8,
+    mut alpha: i32,
+    mut beta: i32,
+    mut pv: Option<&mut Vec<STRATEGY::Move>>,
+    control: &mut SearchControl,
+) -> i32 {
+    if !control.enter_node() {
+        return 0;
+    }
+    strategy.stats().record_node(control.root_level - remaining_levels);
     if remaining_levels == 0 {
+        control.reached_horizon = true;
+        strategy.stats().record_state_scored();
         return strategy.score(state, player) * (i32::from(remaining_levels) + 1);
     }
 
     let alpha_original = alpha;
-    if let Some(entry) = strategy.cache().get(state) {
+    let mut best_move_hint = None;
+    let cached = strategy.cache().get(state);
+    if cached.is_some() {
+        strategy.stats().record_cache_hit();
+    } else {
+        strategy.stats().record_cache_miss();
+    }
+    if let Some(entry) = cached {
+        best_move_hint = entry.best_move;
         if entry.level >= remaining_levels {
+            // the entry does not tell whether its subtree was cut off by the depth limit, so assume it was
+            control.reached_horizon = true;
             match entry.flag {
                 CacheFlag::Exact => return entry.value,
                 CacheFlag::LowerBound => alpha = alpha.max(entry.value),
@@ -98,18 +388,54 @@ fn alpha_beta_eval_single_move<STRATEGY: Strategy>(strategy: &mut STRATEGY, stat
         }
     }
 
+    let mut moves = STRATEGY::possible_moves(state).into_iter().enumerate().collect_vec();
     // Check if this state is terminal i.e. no more moves can be made
-    let mut moves = STRATEGY::possible_moves(state).into_iter().peekable();
-    if moves.peek().is_none() {
+    if moves.is_empty() {
+        strategy.stats().record_state_scored();
         return strategy.score(state, player) * (i32::from(remaining_levels) + 1);
     }
+    // try the best move of an earlier visit first, the remaining moves keep their order
+    if let Some(hint) = best_move_hint.map(usize::from).filter(|hint| *hint < moves.len()) {
+        moves[..=hint].rotate_right(1);
+    }
 
     let mut max_score = -i32::MAX;
-    for m in moves {
+    let mut best_move = None;
+    let mut child_pv = pv.as_ref().map(|_| Vec::new());
+    for (position, (move_index, m)) in moves.into_iter().enumerate() {
         let next_state = strategy.do_move(state, &m, player);
-        max_score = max_score.max(-alpha_beta_eval_single_move(strategy, &next_state, !player, remaining_levels - 1, -beta, -alpha));
+        if let Some(child_pv) = child_pv.as_mut() {
+            child_pv.clear();
+        }
+        let score = if control.algorithm == Algorithm::PrincipalVariation && position > 0 {
+            // assume the earlier moves were better and only prove that with a null window
+            let score = -alpha_beta_eval_single_move(strategy, &next_state, !player, remaining_levels - 1, -alpha - 1, -alpha, child_pv.as_mut(), control);
+            if score > alpha && score < beta && !control.aborted {
+                if let Some(child_pv) = child_pv.as_mut() {
+                    child_pv.clear();
+                }
+                -alpha_beta_eval_single_move(strategy, &next_state, !player, remaining_levels - 1, -beta, -alpha, child_pv.as_mut(), control)
+            } else {
+                score
+            }
+        } else {
+            -alpha_beta_eval_single_move(strategy, &next_state, !player, remaining_levels - 1, -beta, -alpha, child_pv.as_mut(), control)
+        };
+        if control.aborted {
+            return 0;
+        }
+        if score > max_score {
+            max_score = score;
+            best_move = Some(move_index as u8);
+            if let (Some(pv), Some(child_pv)) = (pv.as_mut(), child_pv.as_mut()) {
+                pv.clear();
+                pv.push(m);
+                pv.append(child_pv);
+            }
+        }
         alpha = alpha.max(max_score);
         if alpha >= beta {
+            strategy.stats().record_prune(position);
             break;
         }
     }
@@ -124,6 +450,7 @@ fn alpha_beta_eval_single_move<STRATEGY: Strategy>(strategy: &mut STRATEGY, stat
         level: remaining_levels,
         flag,
         value: max_score,
+        best_move,
     });
     max_score
 }
@@ -142,8 +469,56 @@ pub fn to_score_board(scored_moves: &Vec<ScoredMove<SymmetricMove3x3>>) -> [i32;
 }
 
 pub fn print_3_by_3<E: Display>(scored_board: &[E; 9]) {
+    eprint!("{}", format_3_by_3(scored_board));
+}
+
+pub fn format_3_by_3<E: Display>(scored_board: &[E; 9]) -> String {
     let scores = scored_board;
-    eprintln!("{:>3}, {:>3}, {:>3}", scores[0], scores[1], scores[2]);
-    eprintln!("{:>3}, {:>3}, {:>3}", scores[3], scores[4], scores[5]);
-    eprintln!("{:>3}, {:>3}, {:>3}", scores[6], scores[7], scores[8]);
+    format!(
+        "{:>3}, {:>3}, {:>3}\n{:>3}, {:>3}, {:>3}\n{:>3}, {:>3}, {:>3}\n",
+        scores[0], scores[1], scores[2], scores[3], scores[4], scores[5], scores[6], scores[7], scores[8],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::min_max::{alpha_beta, score_possible_moves, CacheEntry, SearchBudget};
+    use crate::min_max::cache::{Cache, HashMapCache};
+    use crate::min_max::deepening::iterative_deepening;
+    use crate::ultimate_ttt;
+
+    // forgets the best move of every entry
+    #[derive(Default)]
+    struct NoBestMoveCache(HashMapCache<ultimate_ttt::GameBoard>);
+
+    impl Cache<ultimate_ttt::GameBoard> for NoBestMoveCache {
+        fn set(&mut self, state: &ultimate_ttt::GameBoard, entry: CacheEntry) {
+            self.0.set(state, CacheEntry { best_move: None, ..entry })
+        }
+
+        fn get(&mut self, state: &ultimate_ttt::GameBoard) -> Option<CacheEntry> {
+            self.0.get(state)
+        }
+    }
+
+    #[test]
+    fn cached_best_move_reduces_nodes() {
+        let board = ultimate_ttt::GameBoard::empty();
+        let mut with_best_move = ultimate_ttt::Strategy::new(HashMapCache::default());
+        iterative_deepening(&mut with_best_move, &board, SearchBudget::unlimited(), 9).unwrap();
+        let mut without_best_move = ultimate_ttt::Strategy::new(NoBestMoveCache::default());
+        iterative_deepening(&mut without_best_move, &board, SearchBudget::unlimited(), 9).unwrap();
+
+        assert!(with_best_move.stats.node_count() < without_best_move.stats.node_count());
+        assert!(with_best_move.stats.first_move_prune_rate() > without_best_move.stats.first_move_prune_rate());
+    }
+
+    #[test]
+    fn depth_zero_scores_the_moves_statically() {
+        let mut board = ultimate_ttt::GameBoard::empty();
+        let mut strategy = ultimate_ttt::Strategy::new(HashMapCache::default());
+        // every move scores 0, as no sub board is won
+        assert_eq!(alpha_beta(&mut strategy, &mut board, 0).len(), 9);
+        assert!(score_possible_moves(&mut strategy, &board, 0).iter().all(|m| m.score == 0));
+    }
 }
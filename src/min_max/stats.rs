@@ -1,18 +1,27 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
 pub trait Stats {
-    fn record_prune(&mut self);
+    /// A beta cutoff happened after searching the move at `move_index` (0 is the first move tried).
+    fn record_prune(&mut self, move_index: usize);
     fn record_cache_hit(&mut self);
     fn record_cache_miss(&mut self);
     fn record_state_scored(&mut self);
+    /// A node `ply` moves below the searched state was entered.
+    fn record_node(&mut self, ply: u8);
+    fn record_search_time(&mut self, elapsed: Duration);
 }
 
 #[derive(Debug, Default)]
 pub struct NullStats;
 
 impl Stats for NullStats {
-    fn record_prune(&mut self) {}
+    fn record_prune(&mut self, _move_index: usize) {}
     fn record_cache_hit(&mut self) {}
     fn record_cache_miss(&mut self) {}
     fn record_state_scored(&mut self) {}
+    fn record_node(&mut self, _ply: u8) {}
+    fn record_search_time(&mut self, _elapsed: Duration) {}
 }
 
 #[derive(Debug, Default, Clone)]
@@ -21,11 +30,55 @@ pub struct SimpleStats {
     pub cache_hit_count: u64,
     pub cache_miss_count: u64,
     pub state_scored_count: u64,
+    /// Number of nodes entered per ply below the searched state, index 0 is ply 1.
+    pub nodes_per_ply: Vec<u64>,
+    /// Number of cutoffs per position of the cutting move in the move order.
+    pub prunes_per_move_index: Vec<u64>,
+    pub search_count: u64,
+    pub elapsed: Duration,
+}
+
+impl SimpleStats {
+    pub fn node_count(&self) -> u64 {
+        self.nodes_per_ply.iter().sum()
+    }
+
+    /// Geometric mean of the growth in nodes from one ply to the next.
+    pub fn effective_branching_factor(&self) -> Option<f64> {
+        let first = *self.nodes_per_ply.first()?;
+        let last = *self.nodes_per_ply.last()?;
+        if self.nodes_per_ply.len() < 2 || first == 0 {
+            return None;
+        }
+        Some((last as f64 / first as f64).powf(1.0 / (self.nodes_per_ply.len() - 1) as f64))
+    }
+
+    /// Fraction of the cutoffs that were caused by the first move tried.
+    pub fn first_move_prune_rate(&self) -> Option<f64> {
+        let first = *self.prunes_per_move_index.first()?;
+        Some(first as f64 / self.prune_count as f64)
+    }
+
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        let lookups = self.cache_hit_count + self.cache_miss_count;
+        if lookups == 0 {
+            return None;
+        }
+        Some(self.cache_hit_count as f64 / lookups as f64)
+    }
+}
+
+fn increment_at(counts: &mut Vec<u64>, index: usize) {
+    if counts.len() <= index {
+        counts.resize(index + 1, 0);
+    }
+    counts[index] += 1;
 }
 
 impl Stats for SimpleStats {
-    fn record_prune(&mut self) {
+    fn record_prune(&mut self, move_index: usize) {
         self.prune_count += 1;
+        increment_at(&mut self.prunes_per_move_index, move_index);
     }
 
     fn record_cache_hit(&mut self) {
@@ -39,4 +92,44 @@ impl Stats for SimpleStats {
     fn record_state_scored(&mut self) {
         self.state_scored_count += 1;
     }
-}
\ No newline at end of file
+
+    fn record_node(&mut self, ply: u8) {
+        increment_at(&mut self.nodes_per_ply, usize::from(ply.saturating_sub(1)));
+    }
+
+    fn record_search_time(&mut self, elapsed: Duration) {
+        self.search_count += 1;
+        self.elapsed += elapsed;
+    }
+}
+
+impl Display for SimpleStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let nodes = self.node_count();
+        let seconds = self.elapsed.as_secs_f64();
+        writeln!(f, "searches:        {} in {:.3}s", self.search_count, seconds)?;
+        write!(f, "nodes:           {}", nodes)?;
+        if seconds > 0.0 {
+            write!(f, " ({:.0} nodes/s)", nodes as f64 / seconds)?;
+        }
+        writeln!(f)?;
+        for (ply, count) in self.nodes_per_ply.iter().enumerate() {
+            writeln!(f, "  ply {:>3}:       {}", ply + 1, count)?;
+        }
+        match self.effective_branching_factor() {
+            Some(ebf) => writeln!(f, "branching:       {:.2}", ebf)?,
+            None => writeln!(f, "branching:       -")?,
+        }
+        write!(f, "prunes:          {}", self.prune_count)?;
+        if let Some(rate) = self.first_move_prune_rate() {
+            write!(f, " ({:.1}% on first move)", rate * 100.0)?;
+        }
+        writeln!(f)?;
+        write!(f, "cache:           {} hits, {} misses", self.cache_hit_count, self.cache_miss_count)?;
+        if let Some(rate) = self.cache_hit_rate() {
+            write!(f, " ({:.1}% hit rate)", rate * 100.0)?;
+        }
+        writeln!(f)?;
+        write!(f, "states scored:   {}", self.state_scored_count)
+    }
+}
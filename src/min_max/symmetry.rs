@@ -1,9 +1,9 @@
 use std::marker::PhantomData;
 use std::mem;
+use std::sync::LazyLock;
 use ahash::{HashMap, HashSet, HashSetExt};
 use enumset::{EnumSetType, EnumSet};
 use itertools::Itertools;
-use lazy_static::lazy_static;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
@@ -13,6 +13,10 @@ pub trait Symmetry<T> {
     fn expand(&self, normalised: &T) -> Vec<T>;
 }
 
+/// States that can be mapped to a canonical representative of all states that are equivalent to them under symmetry.
+pub trait CanonicalState: Sized {
+    fn canonical_state(&self) -> Self;
+}
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct SymmetricMove<I, S: Symmetry<I>>(pub I, pub S);
@@ -27,6 +31,8 @@ impl<T, S: Symmetry<T>> SymmetricMove<T, S> {
     }
 }
 
+/// The elements of the dihedral group D4 except the identity, i.e. every way a square grid can be mapped onto itself.
+/// Reflections are named after their axis, rotations are clockwise.
 #[derive(EnumIter, EnumSetType, Debug)]
 #[enumset(repr = "u8")]
 pub enum GridSymmetryAxis {
@@ -34,88 +40,166 @@ pub enum GridSymmetryAxis {
     Horizontal,
     DiagonalTopBottom,
     DiagonalBottomTop,
+    Rotation90,
+    Rotation180,
+    Rotation270,
 }
 
 pub type GridSymmetryAxes = EnumSet<GridSymmetryAxis>;
 
 pub trait GridSymmetryAxisContext {
-    type SymmetricPairs: IntoIterator<Item=(usize, usize)>;
+    /// Number of cells of the grid, indices are in `0..CELLS`.
+    const CELLS: usize;
+
+    /// The symmetries the grid has, non square grids can't be reflected on a diagonal or rotated by a quarter turn.
+    /// Empty if there are no tables for the size of the grid, so only the identity is left.
+    fn axes() -> GridSymmetryAxes;
+
+    /// The index `index` is moved to by the symmetry.
+    fn transform(axis: GridSymmetryAxis, index: usize) -> usize;
 
-    fn symmetric_indices(axis: GridSymmetryAxis) -> Self::SymmetricPairs;
+    fn canonicalization_mapping(axes: &GridSymmetryAxes) -> &'static [usize];
 
-    fn canonicalization_mapping(axes: &GridSymmetryAxes) -> &'static Vec<usize>;
+    /// All indices `index` is mapped to by repeatedly applying the symmetry, including `index` itself.
     fn expand_index(axis: GridSymmetryAxis, index: usize) -> Vec<usize> {
         let mut result = vec![index];
-        result.extend(Self::symmetric_indices(axis).into_iter().filter_map(|(first, second)| {
-            if index == first {
-                Some(second)
-            } else if index == second {
-                Some(first)
-            } else {
-                None
-            }
-        }));
-        return result;
+        let mut current = Self::transform(axis, index);
+        while current != index {
+            result.push(current);
+            current = Self::transform(axis, current);
+        }
+        result
     }
 }
 
+/// A grid with `COLUMNS` columns and `ROWS` rows, indexed row by row from the top left.
 #[derive(Debug)]
-pub struct GridSymmetryAxisContext3x3;
+pub struct GridSymmetryAxisContextMxN<const COLUMNS: usize, const ROWS: usize>;
+
+pub type GridSymmetryAxisContext3x3 = GridSymmetryAxisContextMxN<3, 3>;
+pub type GridSymmetryAxisContext4x4 = GridSymmetryAxisContextMxN<4, 4>;
+pub type GridSymmetryAxisContext5x5 = GridSymmetryAxisContextMxN<5, 5>;
+pub type GridSymmetryAxisContext6x6 = GridSymmetryAxisContextMxN<6, 6>;
+pub type GridSymmetryAxisContext7x6 = GridSymmetryAxisContextMxN<7, 6>;
+
+impl<const COLUMNS: usize, const ROWS: usize> GridSymmetryAxisContext for GridSymmetryAxisContextMxN<COLUMNS, ROWS> {
+    const CELLS: usize = COLUMNS * ROWS;
+
+    fn axes() -> GridSymmetryAxes {
+        use GridSymmetryAxis::*;
+        if !Self::has_tables() {
+            EnumSet::empty()
+        } else if COLUMNS == ROWS {
+            EnumSet::all()
+        } else {
+            Vertical | Horizontal | Rotation180
+        }
+    }
 
-impl GridSymmetryAxisContext for GridSymmetryAxisContext3x3 {
-    type SymmetricPairs = [(usize, usize); 3];
+    fn transform(axis: GridSymmetryAxis, index: usize) -> usize {
+        debug_assert!(Self::axes().contains(axis));
+        let (row, column) = (index / COLUMNS, index % COLUMNS);
+        let last_row = ROWS - 1;
+        let last_column = COLUMNS - 1;
+        // the axes other than Vertical, Horizontal and Rotation180 only exist for square grids, so rows and columns
+        // can be exchanged there
+        let (row, column) = match axis {
+            GridSymmetryAxis::Vertical => (row, last_column - column),
+            GridSymmetryAxis::Horizontal => (last_row - row, column),
+            GridSymmetryAxis::DiagonalTopBottom => (column, row),
+            GridSymmetryAxis::DiagonalBottomTop => (last_column - column, last_row - row),
+            GridSymmetryAxis::Rotation90 => (column, last_row - row),
+            GridSymmetryAxis::Rotation180 => (last_row - row, last_column - column),
+            GridSymmetryAxis::Rotation270 => (last_column - column, row),
+        };
+        row * COLUMNS + column
+    }
 
-    fn symmetric_indices(axis: GridSymmetryAxis) -> Self::SymmetricPairs {
-        match axis {
-            GridSymmetryAxis::Vertical => [(0, 2), (3, 5), (6, 8)],
-            GridSymmetryAxis::Horizontal => [(0, 6), (1, 7), (2, 8)],
-            GridSymmetryAxis::DiagonalTopBottom => [(1, 3), (2, 6), (5, 7)],
-            GridSymmetryAxis::DiagonalBottomTop => [(0, 8), (1, 5), (3, 7)],
+    fn canonicalization_mapping(axes: &GridSymmetryAxes) -> &'static [usize] {
+        match Self::tables() {
+            Some(mappings) => mappings.get(axes).expect("map contains all combinations of the supported axes"),
+            None => &IDENTITY[..Self::CELLS],
         }
     }
-    fn canonicalization_mapping(axes: &GridSymmetryAxes) -> &'static Vec<usize> {
-        AXIS_TO_CANONICAL_INDICES_3X3.get(axes).expect("map contains all combinations")
+}
+
+impl<const COLUMNS: usize, const ROWS: usize> GridSymmetryAxisContextMxN<COLUMNS, ROWS> {
+    // checked without the tables, as generating them asks for the axes
+    fn has_tables() -> bool {
+        matches!((COLUMNS, ROWS), (3, 3) | (4, 4) | (5, 5) | (6, 6) | (7, 6))
+    }
+
+    fn tables() -> Option<&'static AxisToCanonicalIndices> {
+        match (COLUMNS, ROWS) {
+            (3, 3) => Some(&AXIS_TO_CANONICAL_INDICES_3X3),
+            (4, 4) => Some(&AXIS_TO_CANONICAL_INDICES_4X4),
+            (5, 5) => Some(&AXIS_TO_CANONICAL_INDICES_5X5),
+            (6, 6) => Some(&AXIS_TO_CANONICAL_INDICES_6X6),
+            (7, 6) => Some(&AXIS_TO_CANONICAL_INDICES_7X6),
+            _ => None,
+        }
     }
 }
 
+// all indices that are equivalent to the given one under any combination of the symmetries, sorted
+fn orbit<Ctx: GridSymmetryAxisContext>(axes: &GridSymmetryAxes, index: usize) -> Vec<usize> {
+    // fixpoint iteration to find all indices that are equivalent to the given one
+    let mut current = HashSet::new();
+    current.insert(index);
+    let mut old = current.clone();
+
+    loop {
+        for index in &old {
+            current.extend(axes.iter().flat_map(|axis| Ctx::expand_index(axis, *index)))
+        }
+
+        if current == old {
+            break;
+        }
+        mem::swap(&mut current, &mut old);
+    }
+    current.into_iter().sorted().collect()
+}
+
+type AxisToCanonicalIndices = HashMap<GridSymmetryAxes, Vec<usize>>;
+
 // generates a two staged mapping
 // symmetry axis -> index -> canonical index
-fn generate_axis_to_canonical_indices<Ctx: GridSymmetryAxisContext>() -> HashMap<GridSymmetryAxes, Vec<usize>> {
-    GridSymmetryAxis::iter().powerset().map(|axis| {
+fn generate_axis_to_canonical_indices<Ctx: GridSymmetryAxisContext>() -> AxisToCanonicalIndices {
+    Ctx::axes().iter().powerset().map(|axis| {
         let axis_set = axis.into_iter().collect::<EnumSet<_>>();
         // for each board index compute the canonical index (considering the symmetries)
-        let canonical_indices = (0..=8).into_iter().map(|original_index| {
-            // fixpoint iteration to find all indices that are equivalent to the given one
-            let mut current = HashSet::new();
-            current.insert(original_index);
-            let mut old = current.clone();
-
-            loop {
-                for index in &old {
-                    current.extend(axis_set.iter().flat_map(|axis| Ctx::expand_index(axis, *index)))
-                }
-
-                if current == old {
-                    break;
-                }
-                mem::swap(&mut current, &mut old);
-            }
+        let canonical_indices = (0..Ctx::CELLS).map(|original_index| {
             // the canonical index is the smallest
-            return *current.iter().min().expect("set always contains original_index");
+            orbit::<Ctx>(&axis_set, original_index)[0]
         }).collect();
 
-        return (axis_set, canonical_indices);
+        (axis_set, canonical_indices)
     }).collect()
 }
 
-lazy_static! {
-    static ref AXIS_TO_CANONICAL_INDICES_3X3: HashMap<GridSymmetryAxes, Vec<usize>> = generate_axis_to_canonical_indices::<GridSymmetryAxisContext3x3>();
-}
+// the mapping of grids without symmetries, every index is its own canonical index
+static IDENTITY: [usize; 128] = {
+    let mut identity = [0; 128];
+    let mut index = 0;
+    while index < identity.len() {
+        identity[index] = index;
+        index += 1;
+    }
+    identity
+};
+
+// one table per supported grid size, generated when the size is used for the first time
+static AXIS_TO_CANONICAL_INDICES_3X3: LazyLock<AxisToCanonicalIndices> = LazyLock::new(generate_axis_to_canonical_indices::<GridSymmetryAxisContext3x3>);
+static AXIS_TO_CANONICAL_INDICES_4X4: LazyLock<AxisToCanonicalIndices> = LazyLock::new(generate_axis_to_canonical_indices::<GridSymmetryAxisContext4x4>);
+static AXIS_TO_CANONICAL_INDICES_5X5: LazyLock<AxisToCanonicalIndices> = LazyLock::new(generate_axis_to_canonical_indices::<GridSymmetryAxisContext5x5>);
+static AXIS_TO_CANONICAL_INDICES_6X6: LazyLock<AxisToCanonicalIndices> = LazyLock::new(generate_axis_to_canonical_indices::<GridSymmetryAxisContext6x6>);
+static AXIS_TO_CANONICAL_INDICES_7X6: LazyLock<AxisToCanonicalIndices> = LazyLock::new(generate_axis_to_canonical_indices::<GridSymmetryAxisContext7x6>);
 
 #[derive(Debug)]
 pub struct GridSymmetry<Ctx: GridSymmetryAxisContext> {
     axes: GridSymmetryAxes,
-    canonical_index: &'static Vec<usize>,
+    canonical_index: &'static [usize],
     ctx: PhantomData<Ctx>,
 }
 
@@ -136,6 +220,7 @@ impl<Ctx: GridSymmetryAxisContext> Eq for GridSymmetry<Ctx> {}
 impl<Ctx: GridSymmetryAxisContext> GridSymmetry<Ctx> {
     pub fn new<A>(axes: A) -> Self where A: Into<GridSymmetryAxes> {
         let set = axes.into();
+        debug_assert!(Ctx::axes().is_superset(set));
         let canonical_index = Ctx::canonicalization_mapping(&set);
         Self { axes: set, canonical_index, ctx: PhantomData::default() }
     }
@@ -143,28 +228,62 @@ impl<Ctx: GridSymmetryAxisContext> GridSymmetry<Ctx> {
     pub fn none() -> Self {
         Self::new(EnumSet::empty())
     }
+
+    pub fn axes(&self) -> GridSymmetryAxes {
+        self.axes
+    }
+
+    /// The symmetries of a grid given row by row.
+    pub fn of_cells<C: Eq>(cells: &[C]) -> Self {
+        use GridSymmetryAxis::*;
+        debug_assert_eq!(cells.len(), Ctx::CELLS);
+        let is_symmetric = |axis: GridSymmetryAxis| {
+            (0..Ctx::CELLS).all(|index| cells[index] == cells[Ctx::transform(axis, index)])
+        };
+        let supported = Ctx::axes();
+        let mut axes = [Vertical, Horizontal, DiagonalTopBottom, DiagonalBottomTop].into_iter()
+            .filter(|axis| supported.contains(*axis) && is_symmetric(*axis))
+            .collect::<GridSymmetryAxes>();
+        // The symmetries of the cells form a subgroup of D4, so the rotations follow from the reflections: the two
+        // straight or the two diagonal reflections compose to a half turn, a straight and a diagonal one to a quarter
+        // turn. Only without any reflection the rotations have to be checked.
+        let is_pair = axes == Vertical | Horizontal || axes == DiagonalTopBottom | DiagonalBottomTop;
+        if axes.len() >= 3 || (axes.len() == 2 && !is_pair) {
+            axes |= Rotation90 | Rotation180 | Rotation270;
+        } else if is_pair {
+            axes |= Rotation180;
+        } else if axes.is_empty() {
+            if supported.contains(Rotation90) && is_symmetric(Rotation90) {
+                axes |= Rotation90 | Rotation180 | Rotation270;
+            } else if supported.contains(Rotation180) && is_symmetric(Rotation180) {
+                axes |= Rotation180;
+            }
+        }
+        Self::new(axes)
+    }
 }
 
 impl<Ctx: GridSymmetryAxisContext> Symmetry<usize> for GridSymmetry<Ctx> {
     fn canonicalize(&self, target: &usize) -> usize {
-        debug_assert!(*target <= 8);
+        debug_assert!(*target < Ctx::CELLS);
         self.canonical_index[*target]
     }
 
     fn expand(&self, normalised: &usize) -> Vec<usize> {
-        self.axes.iter().flat_map(|axis| Ctx::expand_index(axis, *normalised)).collect()
+        orbit::<Ctx>(&self.axes, *normalised)
     }
 }
 
 pub type GridSymmetry3x3 = GridSymmetry<GridSymmetryAxisContext3x3>;
 pub type SymmetricMove3x3 = SymmetricMove<usize, GridSymmetry3x3>;
+pub type GridSymmetry4x4 = GridSymmetry<GridSymmetryAxisContext4x4>;
+pub type GridSymmetry5x5 = GridSymmetry<GridSymmetryAxisContext5x5>;
+pub type GridSymmetry6x6 = GridSymmetry<GridSymmetryAxisContext6x6>;
+pub type GridSymmetry7x6 = GridSymmetry<GridSymmetryAxisContext7x6>;
 
 impl<C: Eq> From<&[C; 9]> for GridSymmetry3x3 {
     fn from(cells: &[C; 9]) -> Self {
-        let axes = GridSymmetryAxis::iter().filter(|symmetry| {
-            GridSymmetryAxisContext3x3::symmetric_indices(*symmetry).iter().all(|(f, s)| cells[*f] == cells[*s])
-        }).collect::<GridSymmetryAxes>();
-        GridSymmetry3x3::new(axes)
+        Self::of_cells(cells)
     }
 }
 
@@ -173,9 +292,9 @@ impl GridSymmetry3x3 {
         if cells1 == cells2 {
             return true;
         }
-        // TODO: also find cases where a combination of symmetries is required
+        // together with the identity the axes form the whole group, so combinations of them are covered as well
         GridSymmetryAxis::iter().any(|axis| {
-            GridSymmetryAxisContext3x3::symmetric_indices(axis).iter().all(|(f, s)| cells1[*f] == cells2[*s])
+            (0..9).all(|index| cells1[index] == cells2[GridSymmetryAxisContext3x3::transform(axis, index)])
         })
     }
 }
@@ -184,7 +303,16 @@ impl GridSymmetry3x3 {
 mod test {
     use enumset::EnumSet;
     use itertools::Itertools;
-    use crate::min_max::symmetry::{GridSymmetry3x3, GridSymmetryAxis, Symmetry};
+    use crate::min_max::symmetry::{GridSymmetry, GridSymmetryAxisContextMxN, GridSymmetry3x3, GridSymmetry4x4, GridSymmetry7x6, GridSymmetryAxis, GridSymmetryAxisContext, GridSymmetryAxisContext3x3, GridSymmetryAxisContext5x5, GridSymmetryAxisContext6x6, GridSymmetryAxisContext7x6, Symmetry};
+
+    #[test]
+    fn grids_without_tables_only_have_the_identity() {
+        type Ctx = GridSymmetryAxisContextMxN<8, 3>;
+        assert_eq!(Ctx::axes(), EnumSet::empty());
+        let symmetry = GridSymmetry::<Ctx>::of_cells(&[0; 24]);
+        assert_eq!(symmetry, GridSymmetry::none());
+        assert!((0..24).all(|index| symmetry.canonicalize(&index) == index && symmetry.expand(&index) == [index]));
+    }
 
     #[test]
     fn grid_symmetry3x3_normalize() {
@@ -216,7 +344,98 @@ mod test {
     }
 
     #[test]
-    fn grid_symmetry3x3_expand() {}
+    fn grid_symmetry3x3_expand() {
+        let none = GridSymmetry3x3::none();
+        assert_eq!(none.expand(&0usize), vec![0]);
+
+        let vertical_horizontal = GridSymmetry3x3::new(GridSymmetryAxis::Vertical | GridSymmetryAxis::Horizontal);
+        assert_eq!(vertical_horizontal.expand(&0usize), vec![0, 2, 6, 8]);
+        assert_eq!(vertical_horizontal.expand(&1usize), vec![1, 7]);
+        assert_eq!(vertical_horizontal.expand(&4usize), vec![4]);
+
+        let half_turn = GridSymmetry3x3::new(GridSymmetryAxis::Rotation180);
+        assert_eq!(half_turn.expand(&0usize), vec![0, 8]);
+        assert_eq!(half_turn.expand(&3usize), vec![3, 5]);
+
+        let quarter_turn = GridSymmetry3x3::new(GridSymmetryAxis::Rotation90);
+        assert_eq!(quarter_turn.expand(&2usize), vec![0, 2, 6, 8]);
+        assert_eq!(quarter_turn.canonicalize(&7usize), 1);
+    }
+
+    #[test]
+    fn grid_symmetry3x3_from_cells() {
+        let pinwheel = [
+            1, 2, 0,
+            0, 0, 2,
+            1, 0, 0
+        ];
+        assert_eq!(GridSymmetry3x3::from(&pinwheel), GridSymmetry3x3::none());
+
+        let half_turn = [
+            1, 2, 0,
+            0, 0, 0,
+            0, 2, 1
+        ];
+        assert_eq!(GridSymmetry3x3::from(&half_turn), GridSymmetry3x3::new(GridSymmetryAxis::Rotation180));
+
+        let empty = [0; 9];
+        assert_eq!(GridSymmetry3x3::from(&empty), GridSymmetry3x3::new(EnumSet::<GridSymmetryAxis>::all()));
+    }
+
+    #[test]
+    fn grid_symmetry3x3_generated_transform() {
+        let rotation90 = (0..9).map(|index| GridSymmetryAxisContext3x3::transform(GridSymmetryAxis::Rotation90, index)).collect_vec();
+        assert_eq!(rotation90, vec![2, 5, 8, 1, 4, 7, 0, 3, 6]);
+        let diagonal = (0..9).map(|index| GridSymmetryAxisContext3x3::transform(GridSymmetryAxis::DiagonalBottomTop, index)).collect_vec();
+        assert_eq!(diagonal, vec![8, 5, 2, 7, 4, 1, 6, 3, 0]);
+    }
+
+    fn assert_permutations<Ctx: GridSymmetryAxisContext>() {
+        for axis in Ctx::axes() {
+            let image = (0..Ctx::CELLS).map(|index| Ctx::transform(axis, index)).sorted().collect_vec();
+            assert_eq!(image, (0..Ctx::CELLS).collect_vec(), "{:?}", axis);
+        }
+    }
+
+    #[test]
+    fn grid_symmetry_transforms_are_permutations() {
+        assert_permutations::<GridSymmetryAxisContext3x3>();
+        assert_permutations::<GridSymmetryAxisContext5x5>();
+        assert_permutations::<GridSymmetryAxisContext6x6>();
+        assert_permutations::<GridSymmetryAxisContext7x6>();
+    }
+
+    #[test]
+    fn grid_symmetry4x4_normalize() {
+        let all = GridSymmetry4x4::new(EnumSet::<GridSymmetryAxis>::all());
+        assert_eq!(all.canonicalize(&15usize), 0);
+        assert_eq!(all.canonicalize(&13usize), 1);
+        assert_eq!(all.canonicalize(&10usize), 5);
+        assert_eq!(all.expand(&1usize), vec![1, 2, 4, 7, 8, 11, 13, 14]);
+
+        let cells = [
+            1, 0, 0, 1,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        assert_eq!(GridSymmetry4x4::of_cells(&cells), GridSymmetry4x4::new(GridSymmetryAxis::Vertical));
+    }
+
+    #[test]
+    fn grid_symmetry7x6_axes() {
+        assert_eq!(GridSymmetryAxisContext7x6::axes(), GridSymmetryAxis::Vertical | GridSymmetryAxis::Horizontal | GridSymmetryAxis::Rotation180);
+
+        let empty = GridSymmetry7x6::of_cells(&[0; 42]);
+        assert_eq!(empty.canonicalize(&6usize), 0);
+        assert_eq!(empty.canonicalize(&41usize), 0);
+        assert_eq!(empty.canonicalize(&38usize), 3);
+        assert_eq!(empty.expand(&1usize), vec![1, 5, 36, 40]);
+
+        let mut cells = [0; 42];
+        cells[38] = 1;
+        assert_eq!(GridSymmetry7x6::of_cells(&cells), GridSymmetry7x6::new(GridSymmetryAxis::Vertical));
+    }
 
     #[test]
     fn grid_symmetry3x3is_same() {
@@ -251,16 +470,50 @@ mod test {
             assert!(GridSymmetry3x3::is_same(&pair[0], &pair[1]));
         });
         
-        // let cells1 = [
-        //     1, 1, 0,
-        //     0, 0, 0,
-        //     0, 0, 0
-        // ];
-        // let cells2 = [
-        //     0, 0, 0,
-        //     0, 0, 0,
-        //     0, 1, 1
-        // ];
-        // assert!(GridSymmetry3x3::is_same(&cells1, &cells2));
+        let cells1 = [
+            1, 1, 0,
+            0, 0, 0,
+            0, 0, 0
+        ];
+        let cells2 = [
+            0, 0, 0,
+            0, 0, 0,
+            0, 1, 1
+        ];
+        assert!(GridSymmetry3x3::is_same(&cells1, &cells2));
+
+        // a quarter turn, not a reflection
+        let cells1 = [
+            1, 2, 0,
+            0, 0, 0,
+            0, 0, 0
+        ];
+        let cells2 = [
+            0, 0, 1,
+            0, 0, 2,
+            0, 0, 0
+        ];
+        assert!(GridSymmetry3x3::is_same(&cells1, &cells2));
+
+        let cells2 = [
+            0, 0, 0,
+            0, 0, 2,
+            0, 0, 1
+        ];
+        assert!(GridSymmetry3x3::is_same(&cells1, &cells2));
+
+        let cells2 = [
+            0, 2, 1,
+            0, 0, 0,
+            0, 0, 0
+        ];
+        assert!(GridSymmetry3x3::is_same(&cells1, &cells2));
+
+        let cells2 = [
+            0, 0, 0,
+            0, 1, 0,
+            2, 0, 0
+        ];
+        assert!(!GridSymmetry3x3::is_same(&cells1, &cells2));
     }
 }
\ No newline at end of file
@@ -0,0 +1,35 @@
+use lazy_static::lazy_static;
+use rand::prelude::*;
+use crate::min_max::Player;
+
+/// A 64-bit hash of a state that is cheap to update when a move changes only a small part of the state.
+pub trait ZobristHash {
+    fn zobrist_hash(&self) -> u64;
+}
+
+/// Maximum number of distinct values a single component of a state may take.
+pub const VALUES_PER_COMPONENT: usize = 16;
+/// Maximum number of components of a state, e.g. the cells of a board.
+pub const COMPONENTS: usize = 128;
+
+lazy_static! {
+    // fixed seed, so hashes are stable across runs
+    static ref KEYS: Vec<u64> = {
+        let mut rng = SmallRng::seed_from_u64(0x5eed_2a11_0b57_1e5a);
+        (0..COMPONENTS * VALUES_PER_COMPONENT + 1).map(|_| rng.random()).collect()
+    };
+}
+
+/// The key to xor into the hash when component `component` of a state (e.g. a cell) has the value `value`.
+pub fn key(component: usize, value: usize) -> u64 {
+    debug_assert!(component < COMPONENTS && value < VALUES_PER_COMPONENT);
+    KEYS[component * VALUES_PER_COMPONENT + value]
+}
+
+/// The key to xor into the hash for the player that made the last move.
+pub fn player_key(player: Player) -> u64 {
+    match player {
+        Player::Min => KEYS[COMPONENTS * VALUES_PER_COMPONENT],
+        Player::Max => 0,
+    }
+}
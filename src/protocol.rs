@@ -0,0 +1,429 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::iter;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use itertools::Itertools;
+use crate::expecti_min_max::{self, Moves};
+use crate::fen::Fen;
+use crate::game_controller::{self, Replay};
+use crate::min_max::{self, Player, SearchBudget};
+use crate::min_max::deepening::iterative_deepening_with;
+
+/// The limits given to `go`, the search runs until it is stopped without any.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Limits {
+    pub depth: Option<u8>,
+    pub movetime: Option<Duration>,
+}
+
+/// A game the engine protocol searches. Moves are read and written in the notation of the game.
+pub trait Session: Send {
+    /// Sets up the position reached by playing `moves` from the position in [Fen] notation, or from the initial
+    /// position without one.
+    fn set_position(&mut self, fen: Option<&str>, moves: &[String]) -> Result<(), String>;
+    /// Searches the position for the side to move and returns its best move, `None` once the game is over. Every
+    /// completed depth is reported to `info` as an `info` line.
+    fn search(&mut self, limits: Limits, stop: Arc<AtomicBool>, info: &mut dyn FnMut(String)) -> Result<Option<String>, String>;
+}
+
+/// A session for the games without chance, searched by iterative deepening.
+pub struct MinMaxSession<S: min_max::Strategy> {
+    strategy: S,
+    state: S::State,
+}
+
+impl<S: Replay> MinMaxSession<S> where <S::Notation as FromStr>::Err: Display {
+    pub fn new(strategy: S) -> Self {
+        Self { strategy, state: S::initial() }
+    }
+}
+
+impl<S: Replay + Send> Session for MinMaxSession<S>
+where
+    S::State: game_controller::State + Clone + Eq + Send,
+    S::Move: Clone,
+    <S::Notation as FromStr>::Err: Display,
+    Fen<S::State>: FromStr<Err=String>,
+{
+    fn set_position(&mut self, fen: Option<&str>, moves: &[String]) -> Result<(), String> {
+        let mut state = match fen {
+            Some(fen) => fen.parse::<Fen<S::State>>()?.0,
+            None => S::initial(),
+        };
+        for m in moves {
+            let notation = m.parse::<S::Notation>().map_err(|e| e.to_string())?;
+            let player = game_controller::State::player(&state).into();
+            state = self.strategy.play(&state, notation, player)?;
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    fn search(&mut self, limits: Limits, stop: Arc<AtomicBool>, info: &mut dyn FnMut(String)) -> Result<Option<String>, String> {
+        let player = game_controller::State::player(&self.state).into();
+        let budget = SearchBudget { time: limits.movetime, nodes: None };
+        let mut best = None;
+        let state = &self.state;
+        iterative_deepening_with(&mut self.strategy, state, player, budget, limits.depth.unwrap_or(u8::MAX), stop, |strategy, iteration| {
+            let Some(first) = iteration.scored_moves.first() else { return };
+            let line: Vec<_> = iter::once(first.min_max_move.clone()).chain(iteration.principal_variation.iter().cloned()).collect();
+            let line = line_notations(strategy, state, player, &line);
+            best = line.first().map(ToString::to_string);
+            info(format!(
+                "info depth {} score {} nodes {} time {} pv {}",
+                iteration.depth, first.score, iteration.nodes, iteration.elapsed.as_millis(), line.iter().join(" "),
+            ));
+        });
+        // not even depth 1 finished in time, any move is better than none
+        let fallback = || S::possible_moves(state).into_iter().next()
+            .and_then(|m| S::notations(state, &m).first().map(ToString::to_string));
+        Ok(best.or_else(fallback))
+    }
+}
+
+// The notations of the moves of `line`, which are the moves of the search and might only stand for a move equal under
+// symmetry. The notation that leads to the same state as the search is preferred, so the rest of the line still fits.
+fn line_notations<S: Replay>(strategy: &mut S, state: &S::State, mut player: Player, line: &[S::Move]) -> Vec<S::Notation>
+where S::State: Clone + Eq, <S::Notation as FromStr>::Err: Display {
+    let mut state = state.clone();
+    let mut notations = vec![];
+    for m in line {
+        let next = strategy.do_move(&state, m, player);
+        let candidates = S::notations(&state, m);
+        let same_state = candidates.iter()
+            .find(|&notation| strategy.play(&state, notation.clone(), player).is_ok_and(|played| played == next));
+        match same_state.or(candidates.first()) {
+            Some(notation) => notations.push(notation.clone()),
+            None => break,
+        }
+        state = next;
+        player = !player;
+    }
+    notations
+}
+
+/// A session for games with chance, like the dice of Knucklebones. Chance is part of the position, e.g. the roll
+/// `r6` in `position startpos moves r6 1 r2`, and has to be played out before `go`. The principal variation ends at the
+/// next chance node.
+pub struct ExpectiSession<S: expecti_min_max::Strategy> {
+    strategy: S,
+    initial: S::State,
+    state: S::State,
+}
+
+impl<S: expecti_min_max::Strategy> ExpectiSession<S> where S::State: Clone {
+    pub fn new(strategy: S, initial: S::State) -> Self {
+        Self { strategy, state: initial.clone(), initial }
+    }
+}
+
+impl<S: expecti_min_max::Strategy + Send> Session for ExpectiSession<S>
+where
+    S::State: game_controller::State + Clone + Send,
+    S::Move: Clone + Display + FromStr,
+    <S::Move as FromStr>::Err: Display,
+    Fen<S::State>: FromStr<Err=String>,
+{
+    fn set_position(&mut self, fen: Option<&str>, moves: &[String]) -> Result<(), String> {
+        let state = match fen {
+            Some(fen) => fen.parse::<Fen<S::State>>()?.0,
+            None => self.initial.clone(),
+        };
+        self.state = play_chance_moves(&mut self.strategy, state, moves)?;
+        Ok(())
+    }
+
+    fn search(&mut self, limits: Limits, stop: Arc<AtomicBool>, info: &mut dyn FnMut(String)) -> Result<Option<String>, String> {
+        if let Moves::Chance(outcomes) = S::possible_moves(&self.state) {
+            return match outcomes.is_empty() {
+                true => Ok(None),
+                false => Err("Chance has to be played out before the search, e.g. the dice rolled".to_string()),
+            };
+        }
+        let player = game_controller::State::player(&self.state).into();
+        let budget = SearchBudget { time: limits.movetime, nodes: None };
+        let mut best = None;
+        expecti_min_max::iterative_deepening_with(&mut self.strategy, &self.state, player, budget, limits.depth.unwrap_or(u8::MAX), stop, |iteration| {
+            let Some(first) = iteration.scored_moves.first() else { return };
+            best = Some(first.min_max_move.to_string());
+            let mut line = iter::once(&first.min_max_move).chain(&iteration.principal_variation);
+            info(format!(
+                "info depth {} score {} nodes {} time {} pv {}",
+                iteration.depth, first.score, iteration.nodes, iteration.elapsed.as_millis(), line.join(" "),
+            ));
+        });
+        // not even depth 1 finished in time, any move is better than none
+        let fallback = || match S::possible_moves(&self.state) {
+            Moves::Player(moves) => moves.into_iter().next().map(|m| m.to_string()),
+            Moves::Chance(_) => None,
+        };
+        Ok(best.or_else(fallback))
+    }
+}
+
+/// Plays `moves` from `state` in a game with chance, where the outcomes of chance, e.g. the dice rolls, are moves too.
+pub fn play_chance_moves<S: expecti_min_max::Strategy>(strategy: &mut S, mut state: S::State, moves: &[String]) -> Result<S::State, String>
+where S::State: game_controller::State, S::Move: FromStr, <S::Move as FromStr>::Err: Display {
+    for m in moves {
+        let m = m.parse::<S::Move>().map_err(|e| e.to_string())?;
+        let to_move: Player = game_controller::State::player(&state).into();
+        // chance plays out for the player after the one that moved last
+        let player = match S::possible_moves(&state) {
+            Moves::Chance(_) => !to_move,
+            Moves::Player(_) => to_move,
+        };
+        state = strategy.try_do_move(&state, &m, player).map_err(|e| e.to_string())?;
+    }
+    Ok(state)
+}
+
+// how often a running search looks for `stop`
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Speaks a line based text protocol, modelled after UCI, on `input` and `output` until `quit` or the end of the input.
+/// The commands are
+/// ```text
+/// newgame <game>                          start a game, created by `new_session`
+/// position startpos [moves <move>...]     set up the position after the moves
+/// position fen <fen> [moves <move>...]    the same from a position in Fen notation
+/// go [depth <plies>] [movetime <ms>]      search it, answered by info lines and bestmove <move> or bestmove none
+/// stop                                    end the running search early
+/// isready                                 answered by readyok, also while searching
+/// quit
+/// ```
+/// Errors are reported as `info string error: ...` and leave the session as it was. The game of `session` can be
+/// searched without a `newgame`.
+pub fn run<W: Write + Send>(
+    input: impl BufRead + Send + 'static,
+    output: W,
+    mut session: Option<Box<dyn Session>>,
+    new_session: impl Fn(&str) -> Result<Box<dyn Session>, String>,
+) {
+    let (sender, lines) = mpsc::channel();
+    // a separate reader, so stop can be read while searching
+    thread::spawn(move || {
+        for line in input.lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let output = Mutex::new(output);
+    let say = |line: &str| {
+        let mut output = output.lock().expect("no thread panicked while writing");
+        // there is no one left to tell if the output is gone
+        let _ = writeln!(output, "{}", line).and_then(|_| output.flush());
+    };
+    // commands that arrived during a search, they are run after it
+    let mut queued = VecDeque::new();
+    while let Some(line) = queued.pop_front().or_else(|| lines.recv().ok()) {
+        let mut words = line.split_whitespace();
+        let result = match words.next() {
+            None | Some("stop") => Ok(()),
+            Some("quit") => break,
+            Some("isready") => {
+                say("readyok");
+                Ok(())
+            }
+            Some("newgame") => words.next().ok_or_else(|| "Missing game".to_string())
+                .and_then(&new_session)
+                .map(|new| session = Some(new)),
+            Some("position") => current(&mut session).and_then(|session| {
+                let (fen, moves) = parse_position(words)?;
+                session.set_position(fen.as_deref(), &moves)
+            }),
+            Some("go") => match current(&mut session).and_then(|session| Ok((session, parse_limits(words)?))) {
+                Ok((session, limits)) => match go(session, limits, &lines, &mut queued, &say) {
+                    Flow::Continue => Ok(()),
+                    Flow::Quit => break,
+                },
+                Err(e) => Err(e),
+            },
+            Some(command) => Err(format!("Unknown command {}", command)),
+        };
+        if let Err(e) = result {
+            say(&format!("info string error: {}", e));
+        }
+    }
+}
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+fn current(session: &mut Option<Box<dyn Session>>) -> Result<&mut Box<dyn Session>, String> {
+    session.as_mut().ok_or_else(|| "No game, start one with newgame <game>".to_string())
+}
+
+// the fields of the fen, `None` for startpos, and the moves
+fn parse_position<'a>(mut words: impl Iterator<Item=&'a str>) -> Result<(Option<String>, Vec<String>), String> {
+    let fen = match words.next() {
+        Some("startpos") => match words.next() {
+            None | Some("moves") => None,
+            Some(word) => return Err(format!("Expected moves instead of {}", word)),
+        },
+        // the fields of the notation are separated by spaces as well
+        Some("fen") => Some(words.by_ref().take_while(|&word| word != "moves").join(" ")),
+        Some(position) => return Err(format!("Unknown position {}, it is startpos or fen", position)),
+        None => return Err("Missing position".to_string()),
+    };
+    Ok((fen, words.map(String::from).collect()))
+}
+
+fn parse_limits<'a>(mut words: impl Iterator<Item=&'a str>) -> Result<Limits, String> {
+    let mut limits = Limits::default();
+    while let Some(word) = words.next() {
+        let value = words.next().ok_or_else(|| format!("Missing value for {}", word));
+        let invalid = |value: &str| format!("Invalid value {} for {}", value, word);
+        match word {
+            "depth" => limits.depth = Some(value.and_then(|value| value.parse().map_err(|_| invalid(value)))?),
+            "movetime" => limits.movetime = Some(Duration::from_millis(value.and_then(|value| value.parse().map_err(|_| invalid(value)))?)),
+            _ => return Err(format!("Unknown limit {}", word)),
+        }
+    }
+    Ok(limits)
+}
+
+// Searches on another thread and keeps reading commands meanwhile. Commands other than stop, isready and quit are
+// queued until the search is done, as is everything after them to keep the order. The end of the input lets the
+// search finish.
+fn go(
+    session: &mut Box<dyn Session>,
+    limits: Limits,
+    lines: &Receiver<String>,
+    queued: &mut VecDeque<String>,
+    say: &(dyn Fn(&str) + Sync),
+) -> Flow {
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut flow = Flow::Continue;
+    thread::scope(|scope| {
+        let search_stop = Arc::clone(&stop);
+        let search = scope.spawn(move || {
+            match session.search(limits, search_stop, &mut |info| say(&info)) {
+                Ok(best) => say(&format!("bestmove {}", best.as_deref().unwrap_or("none"))),
+                Err(e) => {
+                    say(&format!("info string error: {}", e));
+                    say("bestmove none");
+                }
+            }
+        });
+        while !search.is_finished() {
+            match lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => match line.trim() {
+                    _ if !queued.is_empty() => queued.push_back(line),
+                    "stop" => stop.store(true, Ordering::Relaxed),
+                    "quit" => {
+                        stop.store(true, Ordering::Relaxed);
+                        flow = Flow::Quit;
+                    }
+                    "isready" => say("readyok"),
+                    _ => queued.push_back(line),
+                },
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    });
+    flow
+}
+
+#[cfg(test)]
+mod test {
+    use crate::common::BaseStrategy;
+    use crate::knucklebones;
+    use crate::min_max::cache::NullCache;
+    use crate::ttt;
+    use super::*;
+
+    fn new_session(game: &str) -> Result<Box<dyn Session>, String> {
+        match game {
+            "ttt" => Ok(Box::new(MinMaxSession::new(BaseStrategy::<ttt::GameBoard, _>::new(NullCache)))),
+            "knucklebones" => Ok(Box::new(ExpectiSession::new(knucklebones::Strategy::new(), knucklebones::State::empty()))),
+            _ => Err(format!("Unknown game {}", game)),
+        }
+    }
+
+    fn talk(input: &'static str) -> Vec<String> {
+        let mut output = vec![];
+        run(input.as_bytes(), &mut output, None, new_session);
+        String::from_utf8(output).unwrap().lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn finds_winning_move() {
+        let output = talk("isready\nnewgame ttt\nposition startpos moves 1 4 2 5\ngo depth 9\n");
+        assert_eq!(output[0], "readyok");
+        assert!(output[1..output.len() - 1].iter().all(|line| line.starts_with("info depth ")), "{:?}", output);
+        assert!(output[1].starts_with("info depth 1 score "), "{:?}", output);
+        assert!(output[output.len() - 2].ends_with(" pv 3"), "{:?}", output);
+        assert_eq!(output.last().unwrap(), "bestmove 3");
+    }
+
+    #[test]
+    fn principal_variation_is_playable() {
+        let output = talk("newgame ttt\nposition startpos moves 5\ngo depth 9\n");
+        let info = &output[output.len() - 2];
+        let pv = info.split_once(" pv ").unwrap().1;
+        let mut moves = vec!["5".to_string()];
+        moves.extend(pv.split(' ').map(String::from));
+        assert_eq!(moves.len(), 9, "{}", info);
+        let mut session = MinMaxSession::new(BaseStrategy::<ttt::GameBoard, _>::new(NullCache));
+        assert_eq!(session.set_position(None, &moves), Ok(()));
+    }
+
+    #[test]
+    fn starts_from_notation() {
+        let output = talk("newgame ttt\nposition fen xx1/oo1/3 o moves 9\ngo depth 1\nposition fen 3/3/3 o\ngo depth 1\n");
+        assert_eq!(output.len(), 4, "{:?}", output);
+        assert!(output[0].ends_with(" pv 3"), "{}", output[0]);
+        assert_eq!(output[1], "bestmove 3");
+        assert_eq!(output[3], "bestmove 5");
+
+        let output = talk("newgame knucklebones\nposition fen -/-/- 6/-/- x 6\ngo depth 1\n");
+        assert_eq!(output.last().unwrap(), "bestmove 1");
+    }
+
+    #[test]
+    fn plays_placement_after_roll() {
+        let output = talk("newgame knucklebones\nposition startpos moves r6 1 r6\ngo depth 1\nposition startpos moves r6 1\ngo\n");
+        assert_eq!(output.len(), 4, "{:?}", output);
+        assert!(output[0].starts_with("info depth 1 score ") && output[0].ends_with(" pv 1"), "{}", output[0]);
+        assert_eq!(output[1], "bestmove 1");
+        assert_eq!(output[2], "info string error: Chance has to be played out before the search, e.g. the dice rolled");
+        assert_eq!(output[3], "bestmove none");
+    }
+
+    #[test]
+    fn move_time_ends_expecti_search_within_a_depth() {
+        // the deadline is looked at every 1024 nodes, so no depth that needs more of them finishes
+        let output = talk("newgame knucklebones\nposition startpos moves r6\ngo movetime 0\n");
+        let (bestmove, infos) = output.split_last().unwrap();
+        assert!(!infos.is_empty(), "{:?}", output);
+        for info in infos {
+            let nodes = info.split_once(" nodes ").unwrap().1.split(' ').next().unwrap();
+            assert!(nodes.parse::<u64>().unwrap() < 1024, "{}", info);
+        }
+        let pv = infos.last().unwrap().split_once(" pv ").unwrap().1;
+        assert_eq!(*bestmove, format!("bestmove {}", pv.split(' ').next().unwrap()));
+    }
+
+    #[test]
+    fn reports_errors() {
+        let output = talk("go\nnewgame chess\nnewgame ttt\nposition startpos moves 5 5\nposition fen x\ngo depth x\nfoo\nposition startpos moves 5\n");
+        assert_eq!(output, [
+            "info string error: No game, start one with newgame <game>",
+            "info string error: Unknown game chess",
+            "info string error: Cell 5 is already taken",
+            "info string error: Expected 2 fields instead of 1 in x",
+            "info string error: Invalid value x for depth",
+            "info string error: Unknown command foo",
+        ]);
+    }
+}
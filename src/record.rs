@@ -0,0 +1,152 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The results a record can end with, as in PGN. The first player is the one who moved first.
+pub const FIRST_WON: &str = "1-0";
+pub const SECOND_WON: &str = "0-1";
+pub const DRAW: &str = "1/2-1/2";
+pub const ONGOING: &str = "*";
+
+/// A game in a PGN like text format. Tags in square brackets are followed by the moves in the notation of the game,
+/// numbered by pairs of moves and ended by the result:
+/// ```text
+/// [Game "ttt"]
+/// [Players "Human - Computer"]
+/// [Result "0-1"]
+///
+/// 1. 5 1 2. 9 3 3. 7 2 0-1
+/// ```
+/// The moves are kept as text, so records of all games can be read and written the same way.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Record {
+    tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+}
+
+impl Record {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces the value of the tag `name`, new tags are added at the end.
+    pub fn set_tag(&mut self, name: &str, value: impl Into<String>) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, old)) => *old = value.into(),
+            None => self.tags.push((name.to_string(), value.into())),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?.parse()
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| format!("Can't write {}: {}", path, e))
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+        writeln!(f)?;
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                write!(f, "{}. ", i / 2 + 1)?;
+            }
+            write!(f, "{} ", m)?;
+        }
+        writeln!(f, "{}", self.tag("Result").unwrap_or(ONGOING))
+    }
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut record = Record::new();
+        let mut lines = s.lines().map(str::trim).skip_while(|line| line.is_empty()).peekable();
+        while let Some(line) = lines.next_if(|line| line.starts_with('[')) {
+            let (name, value) = parse_tag(line).ok_or_else(|| format!("Invalid tag {}", line))?;
+            record.set_tag(name, value);
+        }
+        for token in lines.flat_map(str::split_whitespace) {
+            let is_move_number = token.strip_suffix('.').is_some_and(|number| number.parse::<usize>().is_ok());
+            let is_result = [FIRST_WON, SECOND_WON, DRAW, ONGOING].contains(&token);
+            if !is_move_number && !is_result {
+                record.moves.push(token.to_string());
+            }
+        }
+        Ok(record)
+    }
+}
+
+// [Name "value"] with \" and \\ escaped in the value
+fn parse_tag(line: &str) -> Option<(&str, String)> {
+    let (name, value) = line.strip_prefix('[')?.strip_suffix(']')?.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some((name, unescaped))
+}
+
+/// The date of `time` in the format of the Date tag, e.g. 2024.03.17.
+pub fn date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() / 86400) as i64;
+    // days since 1970-01-01 to the proleptic gregorian calendar, eras are 400 years long
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut record = Record::new();
+        record.set_tag("Game", "ultimate-ttt");
+        record.set_tag("Players", "Human \"Ann\" - Computer");
+        record.set_tag("Result", ONGOING);
+        record.moves = ["5,5", "5,1", "1,5"].map(String::from).to_vec();
+        let text = record.to_string();
+        assert_eq!(text, "[Game \"ultimate-ttt\"]\n[Players \"Human \\\"Ann\\\" - Computer\"]\n[Result \"*\"]\n\n1. 5,5 5,1 2. 1,5 *\n");
+        assert_eq!(text.parse::<Record>(), Ok(record));
+    }
+
+    #[test]
+    fn parse() {
+        let record = "\n[Game \"ttt\"]\n[Result \"1-0\"]\n\n1. 5 1\n2. 9 3 1-0\n".parse::<Record>().unwrap();
+        assert_eq!(record.tag("Game"), Some("ttt"));
+        assert_eq!(record.tag("Result"), Some(FIRST_WON));
+        assert_eq!(record.tag("Seed"), None);
+        assert_eq!(record.moves, ["5", "1", "9", "3"]);
+        assert!("[Game ttt]".parse::<Record>().is_err());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(UNIX_EPOCH), "1970.01.01");
+        assert_eq!(date(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000.02.29");
+        assert_eq!(date(UNIX_EPOCH + Duration::from_secs(1_710_633_600 + 3600)), "2024.03.17");
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 use std::hash::Hash;
 use rand::prelude::IndexedRandom;
-use rand::seq::SliceRandom;
-use crate::{common, min_max};
-use crate::common::{Board3x3, Cell, BaseStrategy, default_score, Board};
+use rand::rngs::SmallRng;
+use crate::{common, game_controller, min_max};
+use crate::common::{Board3x3, Cell, BaseStrategy, default_score, Board, CellIndex};
+use crate::game_controller::{GameController, Replay, Status};
 
 use crate::min_max::*;
-use crate::min_max::cache::Cache;
+use crate::min_max::Strategy as _;
+use crate::min_max::cache::{Cache, HashMapCache};
 use crate::min_max::stats::NullStats;
 use crate::min_max::symmetry::{GridSymmetry3x3, SymmetricMove, SymmetricMove3x3, Symmetry};
 
@@ -35,10 +38,60 @@ impl common::BoardStatus for BoardStatus {
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    OutOfBounds(usize),
+    CellRed(usize),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is already over"),
+            MoveError::OutOfBounds(index) => write!(f, "Cell {} does not exist", index + 1),
+            MoveError::CellRed(index) => write!(f, "Cell {} is already red", index + 1),
+        }
+    }
+}
+
 impl Cell for CellState {
     fn empty() -> Self {
         Self::EMPTY
     }
+
+    fn ordinal(&self) -> usize {
+        *self as usize
+    }
+
+    fn symbol(&self) -> Option<char> {
+        match self {
+            CellState::EMPTY => None,
+            CellState::GREEN => Some('g'),
+            CellState::YELLOW => Some('y'),
+            CellState::RED => Some('r'),
+        }
+    }
+
+    fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            'g' => Some(CellState::GREEN),
+            'y' => Some(CellState::YELLOW),
+            'r' => Some(CellState::RED),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CellState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellState::EMPTY => f.pad(" "),
+            CellState::GREEN => f.pad("G"),
+            CellState::YELLOW => f.pad("Y"),
+            CellState::RED => f.pad("R"),
+        }
+    }
 }
 
 pub type GameBoard = Board3x3<CellState>;
@@ -52,9 +105,9 @@ impl Board for GameBoard {
     }
 
     fn status(&self) -> BoardStatus {
-        match self.winning_indices() {
+        match self.winner() {
             None => BoardStatus::Ongoing,
-            Some(_indices) => match self.last_player {
+            Some(_) => match self.last_player {
                 Player::Min => BoardStatus::MinWon,
                 Player::Max => BoardStatus::MaxWon,
             }
@@ -68,13 +121,16 @@ pub type Strategy<CACHE> = BaseStrategy<GameBoard, CACHE>;
 impl <CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
     type State = GameBoard;
     type Move = SymmetricMove3x3;
+    type MoveError = MoveError;
     type Cache = CACHE;
     type Stats = NullStats;
 
     fn possible_moves(state: &GameBoard) -> impl Iterator<Item=SymmetricMove3x3> {
         let symmetry = state.symmetry();
         let mut covered_index = [false; 9];
-        let moves = state.cells.iter().enumerate().filter_map(move |(index, &cell_state)| {
+        // nothing can be played once a line is complete, the board tracks its winning line so this is cheap
+        let ongoing = state.winner().is_none();
+        let moves = state.cells().iter().enumerate().filter(move |_| ongoing).filter_map(move |(index, &cell_state)| {
             if cell_state == CellState::RED {
                 return None;
             }
@@ -90,16 +146,29 @@ impl <CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
 
     fn do_move(&mut self, state: &GameBoard, SymmetricMove(index, _): &SymmetricMove3x3, player: Player) -> GameBoard {
         let mut new_state = state.clone();
-        new_state.cells[*index] = match state.cells[*index] {
+        new_state.set_cell(*index, match state.cells()[*index] {
             CellState::EMPTY => CellState::GREEN,
             CellState::GREEN => CellState::YELLOW,
             CellState::YELLOW => CellState::RED,
             CellState::RED => panic!(),
-        };
+        });
         new_state.last_player = player;
         return new_state;
     }
 
+    fn try_do_move(&mut self, state: &GameBoard, m: &SymmetricMove3x3, player: Player) -> Result<GameBoard, MoveError> {
+        let index = *m.index();
+        if state.status() != BoardStatus::Ongoing {
+            Err(MoveError::GameOver)
+        } else if index >= GameBoard::CELLS {
+            Err(MoveError::OutOfBounds(index))
+        } else if state.cells()[index] == CellState::RED {
+            Err(MoveError::CellRed(index))
+        } else {
+            Ok(self.do_move(state, m, player))
+        }
+    }
+
     fn score(&mut self, state: &GameBoard, player: Player) -> i32 {
         default_score(state.status(), player)
     }
@@ -113,13 +182,6 @@ impl <CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
     }
 }
 
-pub fn choose_random_move(moves: Vec<ScoredMove<SymmetricMove3x3>>) -> ScoredMove<usize> {
-    all_move_indices(moves)
-        .choose(&mut rand::rng())
-        .unwrap()
-        .clone()
-}
-
 pub fn all_move_indices(moves: Vec<ScoredMove<SymmetricMove3x3>>) -> Vec<ScoredMove<usize>> {
     moves.iter()
         .flat_map(|m| m.min_max_move.expanded_indices().into_iter().map(move |i| ScoredMove::new(m.score, i)))
@@ -128,22 +190,108 @@ pub fn all_move_indices(moves: Vec<ScoredMove<SymmetricMove3x3>>) -> Vec<ScoredM
         .collect::<Vec<_>>()
 }
 
+impl game_controller::State for GameBoard {
+    fn player(&self) -> game_controller::Player {
+        match self.last_player {
+            Player::Max => game_controller::Player::Human,
+            Player::Min => game_controller::Player::Computer,
+        }
+    }
+
+    fn status(&self) -> Status {
+        match Board::status(self) {
+            BoardStatus::MaxWon => Status::Done { winner: game_controller::Player::Computer },
+            BoardStatus::MinWon => Status::Done { winner: game_controller::Player::Human },
+            BoardStatus::Ongoing => Status::Playing,
+        }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> Replay for Strategy<CACHE> {
+    type Notation = CellIndex;
+
+    fn initial() -> GameBoard {
+        GameBoard::empty()
+    }
+
+    fn play(&mut self, state: &GameBoard, CellIndex(index): CellIndex, player: Player) -> Result<GameBoard, String> {
+        self.try_do_move(state, &SymmetricMove(index, GridSymmetry3x3::none()), player).map_err(|e| e.to_string())
+    }
+
+    fn notations(_state: &GameBoard, m: &SymmetricMove3x3) -> Vec<CellIndex> {
+        m.expanded_indices().into_iter().map(CellIndex).collect()
+    }
+}
+
+/// The human plays [Player::Min] and moves first, the computer searches `depth` moves ahead.
+pub struct Stoplight<CACHE: Cache<GameBoard> = HashMapCache<GameBoard>> {
+    rng: SmallRng,
+    strategy: Strategy<CACHE>,
+    depth: u8,
+}
+
+impl Stoplight {
+    pub fn new(rng: SmallRng, depth: u8) -> Self {
+        Self::with_cache(rng, HashMapCache::default(), depth)
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> Stoplight<CACHE> {
+    pub fn with_cache(rng: SmallRng, cache: CACHE, depth: u8) -> Self {
+        Self { rng, strategy: Strategy::new(cache), depth }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> GameController for Stoplight<CACHE> {
+    type State = GameBoard;
+    type Move = CellIndex;
+
+    fn initial(&mut self) -> Self::State {
+        Strategy::<CACHE>::initial()
+    }
+
+    fn do_move(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, Player::Min)
+    }
+
+    fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move) {
+        let moves = alpha_beta(&mut self.strategy, &mut state.clone(), self.depth);
+        let index = all_move_indices(moves).choose(&mut self.rng).expect("the computer only moves while the game is ongoing").min_max_move;
+        let state = self.strategy.play(state, CellIndex(index), Player::Max).expect("the search only finds legal moves");
+        (state, CellIndex(index))
+    }
+
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, game_controller::State::player(state).into())
+    }
+
+    fn hint(&mut self, state: &Self::State) -> String {
+        let scored_cells = game_controller::score_notations(&mut self.strategy, state, self.depth, Player::Min).into_iter()
+            .map(|(cell, score)| (cell.0, score))
+            .collect::<Vec<_>>();
+        game_controller::format_hint_grid(&scored_cells)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
     use std::time::Instant;
-    use crate::min_max::{alpha_beta, Player, score_possible_moves};
-    use crate::min_max::cache::{HashMapCache};
-    use crate::stoplight::{GameBoard, Cells, CellState, print_3_by_3, Strategy, to_score_board};
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+    use crate::min_max::{alpha_beta, Player, score_possible_moves, score_possible_moves_with, Algorithm, SearchConfig};
+    use crate::min_max::cache::{HashMapCache, ReplacementPolicy, SymmetricCache, TranspositionTable};
+    use crate::fen::Fen;
+    use crate::stoplight::{GameBoard, Cells, CellState, print_3_by_3, Stoplight, Strategy, to_score_board};
 
     fn best_move_index_of(cells: Cells) -> usize {
-        let m = alpha_beta(&mut Strategy::new(HashMapCache::default()), &mut GameBoard::new(cells, Player::Max), 30);
+        let m = alpha_beta(&mut Strategy::new(HashMapCache::default()), &mut GameBoard::new(&cells, Player::Max), 30);
         print_3_by_3(&to_score_board(&m));
         return *m[0].min_max_move.index();
     }
 
     fn score_board(cells: Cells) -> [i32; 9] {
-        to_score_board(&score_possible_moves(&mut Strategy::new(HashMapCache::default()), &mut GameBoard::new(cells, Player::Max), 30))
+        to_score_board(&score_possible_moves(&mut Strategy::new(HashMapCache::default()), &mut GameBoard::new(&cells, Player::Max), 30))
     }
 
     #[test]
@@ -264,4 +412,67 @@ mod tests {
             assert!(m.score == min_score || m.min_max_move == 4)
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn empty_board_transposition_table() {
+        let board = GameBoard::empty();
+        let expected = score_possible_moves(&mut Strategy::new(HashMapCache::default()), &board, u8::MAX);
+        let table = TranspositionTable::with_capacity_mb(32, ReplacementPolicy::DepthPreferred);
+        let scored_moves = score_possible_moves(&mut Strategy::new(table), &board, u8::MAX);
+
+        assert_eq!(scored_moves, expected);
+    }
+
+    #[test]
+    fn empty_board_symmetric_cache() {
+        let board = GameBoard::empty();
+        let mut plain = Strategy::new(HashMapCache::default());
+        let expected = score_possible_moves(&mut plain, &board, u8::MAX);
+        let mut symmetric = Strategy::new(SymmetricCache::new(HashMapCache::default()));
+        let scored_moves = score_possible_moves(&mut symmetric, &board, u8::MAX);
+
+        assert_eq!(scored_moves, expected);
+        let cache = symmetric.cache();
+        assert!(cache.symmetric_hit_count() > 0);
+        assert!(cache.inner().len() < plain.cache().len(), "{} vs {}", cache.inner().len(), plain.cache().len());
+    }
+
+    #[test]
+    fn empty_board_search_algorithms() {
+        let board = GameBoard::empty();
+        let expected = score_possible_moves(&mut Strategy::new(HashMapCache::default()), &board, u8::MAX);
+        for algorithm in [Algorithm::PrincipalVariation, Algorithm::Mtdf] {
+            let scored_moves = score_possible_moves_with(&mut Strategy::new(HashMapCache::default()), &board, u8::MAX, SearchConfig::new(algorithm));
+            assert_eq!(scored_moves, expected, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn controller_rejects_red_cell() {
+        use crate::common::CellIndex;
+        use crate::game_controller::GameController;
+
+        let mut game = Stoplight::new(SmallRng::seed_from_u64(0), u8::MAX);
+        let Fen(state) = "r2/1g1/3 o".parse::<Fen<GameBoard>>().unwrap();
+        assert!(game.do_move(&state, CellIndex(0)).is_err());
+
+        let state = game.do_move(&state, CellIndex(4)).unwrap();
+        assert_eq!(state.cells()[4], CellState::YELLOW);
+        assert_eq!(state.last_player, Player::Min);
+        let (state, CellIndex(index)) = game.do_computer_move(&state);
+        assert_ne!(state.cells()[index], CellState::EMPTY);
+        assert_eq!(state.last_player, Player::Max);
+    }
+
+    #[test]
+    fn position_notation() {
+        let state = GameBoard::new(&[
+            CellState::EMPTY, CellState::YELLOW, CellState::RED,
+            CellState::EMPTY, CellState::EMPTY, CellState::EMPTY,
+            CellState::GREEN, CellState::EMPTY, CellState::EMPTY,
+        ], Player::Min);
+        assert_eq!(Fen(state).to_string(), "1yr/3/g2 x");
+        assert_eq!("1yr/3/g2 x".parse(), Ok(Fen(state)));
+        assert!("1yx/3/g2 x".parse::<Fen<GameBoard>>().is_err());
+    }
+}
@@ -1,9 +1,12 @@
-use crate::{common, min_max};
-use crate::common::{Board3x3, Cell, BaseStrategy, default_score, Board};
-use crate::min_max::{Player};
-use crate::min_max::cache::{NullCache};
+use std::fmt::{Display, Formatter};
+use rand::prelude::*;
+use crate::{common, game_controller, min_max};
+use crate::common::{Board3x3, BoardMxN, Cell, BaseStrategy, default_score, Board, CellIndex};
+use crate::game_controller::{GameController, Replay, Status};
+use crate::min_max::{alpha_beta, Player, Strategy as _};
+use crate::min_max::cache::{Cache, NullCache};
 use crate::min_max::stats::NullStats;
-use crate::min_max::symmetry::{GridSymmetry3x3, SymmetricMove, SymmetricMove3x3, Symmetry};
+use crate::min_max::symmetry::{GridSymmetry, GridSymmetryAxisContextMxN, SymmetricMove, Symmetry};
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub enum BoardStatus {
@@ -23,6 +26,23 @@ impl common::BoardStatus for BoardStatus {
     }
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    OutOfBounds(usize),
+    CellTaken(usize),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is already over"),
+            MoveError::OutOfBounds(index) => write!(f, "Cell {} does not exist", index + 1),
+            MoveError::CellTaken(index) => write!(f, "Cell {} is already taken", index + 1),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub enum CellState {
     EMPTY,
@@ -34,6 +54,36 @@ impl Cell for CellState {
     fn empty() -> Self {
         Self::EMPTY
     }
+
+    fn ordinal(&self) -> usize {
+        *self as usize
+    }
+
+    fn symbol(&self) -> Option<char> {
+        match self {
+            CellState::EMPTY => None,
+            CellState::X => Some('x'),
+            CellState::O => Some('o'),
+        }
+    }
+
+    fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            'x' => Some(CellState::X),
+            'o' => Some(CellState::O),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CellState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CellState::EMPTY => f.pad(" "),
+            CellState::X => f.pad("X"),
+            CellState::O => f.pad("O"),
+        }
+    }
 }
 
 impl From<Player> for CellState {
@@ -46,9 +96,11 @@ impl From<Player> for CellState {
 }
 
 pub type GameBoard = Board3x3<CellState>;
+/// Tic-tac-toe on a board with `M` columns and `N` rows where `K` in a row win, e.g. 4x4x4 or gomoku on small boards.
+pub type GameBoardMxN<const M: usize, const N: usize, const K: usize> = BoardMxN<CellState, M, N, K>;
 
-impl Board for GameBoard {
-    type Move = SymmetricMove<usize, GridSymmetry3x3>;
+impl<const M: usize, const N: usize, const K: usize> Board for GameBoardMxN<M, N, K> {
+    type Move = SymmetricMove<usize, GridSymmetry<GridSymmetryAxisContextMxN<M, N>>>;
     type BoardStatus = BoardStatus;
 
     fn last_player(&self) -> Player {
@@ -56,14 +108,14 @@ impl Board for GameBoard {
     }
 
     fn status(&self) -> Self::BoardStatus {
-        match self.winning_indices() {
-            Some(indices) => match self.cells[indices[0]] {
+        match self.winner() {
+            Some(winner) => match winner {
                 CellState::X => BoardStatus::MaxWon,
                 CellState::O => BoardStatus::MinWon,
                 _ => panic!(),
             },
             None => {
-                if self.cells.iter().any(|c| c == &CellState::EMPTY) {
+                if self.cells().iter().any(|c| c == &CellState::EMPTY) {
                     BoardStatus::Ongoing
                 } else {
                     BoardStatus::Draw
@@ -73,54 +125,81 @@ impl Board for GameBoard {
     }
 }
 
-pub type Strategy = BaseStrategy<GameBoard, NullCache>;
+pub type Strategy = StrategyMxN<3, 3, 3>;
+pub type StrategyMxN<const M: usize, const N: usize, const K: usize> = BaseStrategy<GameBoardMxN<M, N, K>, NullCache>;
 
-impl Strategy {
+impl<const M: usize, const N: usize, const K: usize> StrategyMxN<M, N, K> {
     pub fn score_board_state(status: BoardStatus, player: Player) -> i32 {
         default_score(status, player)
     }
 }
 
-impl Default for Strategy {
+impl<const M: usize, const N: usize, const K: usize> Default for StrategyMxN<M, N, K> {
     fn default() -> Self {
         Self::new(NullCache::default())
     }
 }
 
-impl min_max::Strategy for Strategy {
-    type State = GameBoard;
-    type Move = SymmetricMove3x3;
-    type Cache = NullCache;
+// the cells from the center outwards, rings are in row order
+fn move_order<const M: usize, const N: usize>() -> impl Iterator<Item=usize> {
+    let distance = |index: usize| {
+        let (row, column) = (index / M, index % M);
+        (2 * column).abs_diff(M - 1).max((2 * row).abs_diff(N - 1))
+    };
+    (0..=M.max(N)).flat_map(move |ring| (0..M * N).filter(move |index| distance(*index) == ring))
+}
+
+impl<CACHE: Cache<GameBoardMxN<M, N, K>>, const M: usize, const N: usize, const K: usize> min_max::Strategy for BaseStrategy<GameBoardMxN<M, N, K>, CACHE> {
+    type State = GameBoardMxN<M, N, K>;
+    type Move = SymmetricMove<usize, GridSymmetry<GridSymmetryAxisContextMxN<M, N>>>;
+    type MoveError = MoveError;
+    type Cache = CACHE;
     type Stats = NullStats;
 
-    fn possible_moves(state: &GameBoard) -> impl IntoIterator<Item=SymmetricMove3x3> + 'static {
+    fn possible_moves(state: &Self::State) -> impl IntoIterator<Item=Self::Move> + 'static {
         let symmetry = state.symmetry();
-        let mut covered_index = [false; 9];
-        let cells = state.cells;
-        [4, 0, 1, 2, 3, 5, 6, 7, 8].into_iter().filter_map(move |index| {
-            if cells[index] != CellState::EMPTY {
+        // one bit per cell, boards are limited to 128 cells by Zobrist hashing anyway
+        let mut covered_index = 0u128;
+        let state = *state;
+        // nothing can be played once a line is complete, the board tracks its winning line so this is cheap
+        let ongoing = state.winner().is_none();
+        move_order::<M, N>().filter(move |_| ongoing).filter_map(move |index| {
+            if state.cells()[index] != CellState::EMPTY {
                 return None;
             }
             let normalised = symmetry.canonicalize(&index);
-            if covered_index[normalised] {
+            if covered_index & (1 << normalised) != 0 {
                 return None;
             }
-            covered_index[normalised] = true;
+            covered_index |= 1 << normalised;
             return Some(SymmetricMove(normalised, symmetry.clone()));
         })
     }
 
-    fn do_move(&mut self, state: &GameBoard, SymmetricMove(index, _): &SymmetricMove3x3, player: Player) -> GameBoard {
+    fn do_move(&mut self, state: &Self::State, SymmetricMove(index, _): &Self::Move, player: Player) -> Self::State {
         let mut new_state = state.clone();
-        new_state.cells[*index] = match state.cells[*index] {
+        new_state.set_cell(*index, match state.cells()[*index] {
             CellState::EMPTY => CellState::from(player),
             _ => panic!(),
-        };
+        });
         new_state.last_player = player;
         return new_state;
     }
 
-    fn score(&mut self, state: &GameBoard, player: Player) -> i32 {
+    fn try_do_move(&mut self, state: &Self::State, m: &Self::Move, player: Player) -> Result<Self::State, MoveError> {
+        let index = *m.index();
+        if state.status() != BoardStatus::Ongoing {
+            Err(MoveError::GameOver)
+        } else if index >= GameBoardMxN::<M, N, K>::CELLS {
+            Err(MoveError::OutOfBounds(index))
+        } else if state.cells()[index] != CellState::EMPTY {
+            Err(MoveError::CellTaken(index))
+        } else {
+            Ok(self.do_move(state, m, player))
+        }
+    }
+
+    fn score(&mut self, state: &Self::State, player: Player) -> i32 {
         default_score(state.status(), player)
     }
 
@@ -133,35 +212,144 @@ impl min_max::Strategy for Strategy {
     }
 }
 
+impl<const M: usize, const N: usize, const K: usize> game_controller::State for GameBoardMxN<M, N, K> {
+    fn player(&self) -> game_controller::Player {
+        match self.last_player {
+            Player::Max => game_controller::Player::Human,
+            Player::Min => game_controller::Player::Computer,
+        }
+    }
+
+    fn status(&self) -> Status {
+        match Board::status(self) {
+            BoardStatus::MaxWon => Status::Done { winner: game_controller::Player::Computer },
+            BoardStatus::MinWon => Status::Done { winner: game_controller::Player::Human },
+            BoardStatus::Draw => Status::Draw,
+            BoardStatus::Ongoing => Status::Playing,
+        }
+    }
+}
+
+impl<CACHE: Cache<GameBoardMxN<M, N, K>>, const M: usize, const N: usize, const K: usize> Replay for BaseStrategy<GameBoardMxN<M, N, K>, CACHE> {
+    type Notation = CellIndex;
+
+    fn initial() -> Self::State {
+        GameBoardMxN::empty()
+    }
+
+    fn play(&mut self, state: &Self::State, CellIndex(index): CellIndex, player: Player) -> Result<Self::State, String> {
+        self.try_do_move(state, &SymmetricMove(index, GridSymmetry::none()), player).map_err(|e| e.to_string())
+    }
+
+    fn notations(_state: &Self::State, m: &Self::Move) -> Vec<CellIndex> {
+        m.expanded_indices().into_iter().map(CellIndex).collect()
+    }
+}
+
+/// The human plays [Player::Min] with `O` and moves first, the computer searches `depth` moves ahead.
+pub struct TicTacToe<CACHE: Cache<GameBoard> = NullCache> {
+    rng: SmallRng,
+    strategy: BaseStrategy<GameBoard, CACHE>,
+    depth: u8,
+}
+
+impl TicTacToe {
+    pub fn new(rng: SmallRng, depth: u8) -> Self {
+        Self::with_cache(rng, NullCache, depth)
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> TicTacToe<CACHE> {
+    pub fn with_cache(rng: SmallRng, cache: CACHE, depth: u8) -> Self {
+        Self { rng, strategy: BaseStrategy::new(cache), depth }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> GameController for TicTacToe<CACHE> {
+    type State = GameBoard;
+    type Move = CellIndex;
+
+    fn initial(&mut self) -> Self::State {
+        BaseStrategy::<GameBoard, CACHE>::initial()
+    }
+
+    fn do_move(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, Player::Min)
+    }
+
+    fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move) {
+        let moves = alpha_beta(&mut self.strategy, &mut state.clone(), self.depth);
+        let best = moves.choose(&mut self.rng).expect("the computer only moves while the game is ongoing");
+        let m = *BaseStrategy::<GameBoard, CACHE>::notations(state, &best.min_max_move).choose(&mut self.rng).expect("a move expands to itself");
+        let state = self.strategy.play(state, m, Player::Max).expect("the search only finds legal moves");
+        (state, m)
+    }
+
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, game_controller::State::player(state).into())
+    }
+
+    fn hint(&mut self, state: &Self::State) -> String {
+        let scored_cells = game_controller::score_notations(&mut self.strategy, state, self.depth, Player::Min).into_iter()
+            .map(|(cell, score)| (cell.0, score))
+            .collect::<Vec<_>>();
+        game_controller::format_hint_grid(&scored_cells)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
     use std::time::Instant;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
     use crate::common::Board;
+    use crate::fen::Fen;
 
-    use crate::min_max::{Player, score_possible_moves};
-    use crate::ttt::{BoardStatus, GameBoard, Strategy};
+    use crate::min_max::{Player, score_possible_lines, score_possible_moves, score_possible_moves_with, Algorithm, SearchBudget, SearchConfig, Strategy as _};
+    use crate::min_max::deepening::{iterative_deepening, iterative_deepening_for};
+    use crate::ttt::{BoardStatus, GameBoard, GameBoardMxN, Strategy, StrategyMxN, TicTacToe};
 
     #[test]
     fn status() {
-        use crate::ttt::CellState::*;
-        let board = GameBoard::new([EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY], Player::Max);
-        assert_eq!(board.status(), BoardStatus::Ongoing);
+        assert_eq!(board("3/3/3 o").status(), BoardStatus::Ongoing);
+        assert_eq!(board("xxx/3/3 o").status(), BoardStatus::MaxWon);
+        assert_eq!(board("oxx/xoo/xxo x").status(), BoardStatus::MinWon);
+        assert_eq!(board("ooo/3/3 x").status(), BoardStatus::MinWon);
+        assert_eq!(board("xoo/oxx/1oo x").status(), BoardStatus::Ongoing);
+        assert_eq!(board("xoo/oxx/xoo o").status(), BoardStatus::Draw);
+    }
 
-        let board = GameBoard::new([X, X, X, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY], Player::Max);
-        assert_eq!(board.status(), BoardStatus::MaxWon);
+    fn board(fen: &str) -> GameBoard {
+        fen.parse::<Fen<GameBoard>>().unwrap().0
+    }
 
-        let board = GameBoard::new([O, X, X, X, O, O, X, X, O], Player::Min);
-        assert_eq!(board.status(), BoardStatus::MinWon);
+    #[test]
+    fn position_notation() {
+        use crate::ttt::CellState::*;
+        let board = GameBoard::new(&[X, EMPTY, EMPTY, EMPTY, O, EMPTY, EMPTY, O, X], Player::Min);
+        assert_eq!(Fen(board).to_string(), "x2/1o1/1ox x");
+        assert_eq!("x2/1o1/1ox x".parse(), Ok(Fen(board)));
+        assert_eq!(Fen(GameBoard::empty()).to_string(), "3/3/3 o");
 
-        let board = GameBoard::new([O, O, O, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY], Player::Min);
-        assert_eq!(board.status(), BoardStatus::MinWon);
+        let Fen(board) = "4/4/2xo/4 x".parse::<Fen<GameBoardMxN<4, 4, 3>>>().unwrap();
+        assert_eq!(board.cells()[10..12], [X, O]);
+        assert_eq!(Fen(board).to_string(), "4/4/2xo/4 x");
 
-        let board = GameBoard::new([X, O, O, O, X, X, EMPTY, O, O], Player::Min);
-        assert_eq!(board.status(), BoardStatus::Ongoing);
+        assert!("x2/1o1 x".parse::<Fen<GameBoard>>().is_err());
+        assert!("x2/1o1/1oy x".parse::<Fen<GameBoard>>().is_err());
+        assert!("x2/1o1/1ox".parse::<Fen<GameBoard>>().is_err());
+    }
 
-        let board = GameBoard::new([X, O, O, O, X, X, X, O, O], Player::Max);
-        assert_eq!(board.status(), BoardStatus::Draw);
+    #[test]
+    fn larger_boards_are_played_by_cell_index() {
+        use crate::common::CellIndex;
+        use crate::game_controller::Replay;
+        use crate::ttt::CellState::*;
+        let mut strategy = StrategyMxN::<4, 4, 3>::default();
+        let board = strategy.play(&StrategyMxN::<4, 4, 3>::initial(), "16".parse::<CellIndex>().unwrap(), Player::Min).unwrap();
+        assert_eq!(board.cells()[15], O);
+        assert_eq!(strategy.play(&board, "17".parse().unwrap(), Player::Max), Err("Cell 17 does not exist".to_string()));
     }
 
     #[test]
@@ -179,4 +367,196 @@ mod test {
         let scores: Vec<i32> = scored_expanded_moves.iter().map(|scored_move| scored_move.score).collect();
         assert_eq!(scores, vec![0; 9]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn iterative_deepening_solves_empty_board() {
+        let board = GameBoard::empty();
+        let result = iterative_deepening(&mut Strategy::default(), &board, SearchBudget::unlimited(), u8::MAX).unwrap();
+
+        // the game tree is exhausted long before the depth limit
+        assert_eq!(result.depth, 10);
+        assert!(result.scored_moves.iter().all(|m| m.score == 0));
+
+        for algorithm in [Algorithm::PrincipalVariation, Algorithm::Mtdf] {
+            let config = SearchConfig::new(algorithm);
+            let result = iterative_deepening_for(&mut Strategy::default(), &board, Player::Max, SearchBudget::unlimited(), u8::MAX, config).unwrap();
+            assert!(result.scored_moves.iter().all(|m| m.score == 0), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn principal_variation_leads_to_scored_leaf() {
+        use crate::ttt::CellState::*;
+        let board = GameBoard::new(&[X, X, EMPTY, O, O, EMPTY, EMPTY, EMPTY, EMPTY], Player::Min);
+        let mut strategy = Strategy::default();
+        let max_level = 10;
+        let lines = score_possible_lines(&mut strategy, &board, max_level);
+        assert!(lines.iter().map(|l| l.score).max().unwrap() > 0);
+
+        for line in lines {
+            let mut state = strategy.do_move(&board, &line.min_max_move, Player::Max);
+            let mut player = Player::Max;
+            for m in &line.principal_variation {
+                player = !player;
+                state = strategy.do_move(&state, m, player);
+            }
+            // without a cache the variation always runs until the game is over
+            assert_ne!(state.status(), BoardStatus::Ongoing);
+            let remaining_levels = (max_level - 1) as i32 - line.principal_variation.len() as i32;
+            assert_eq!(line.score, strategy.score(&state, Player::Max) * (remaining_levels + 1));
+        }
+    }
+
+    #[test]
+    fn mcts_finds_winning_move() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use crate::mcts;
+        use crate::ttt::CellState::*;
+
+        // X to move, 2 wins immediately
+        let board = GameBoard::new(&[X, X, EMPTY, O, O, EMPTY, EMPTY, EMPTY, EMPTY], Player::Min);
+        let mut rng = SmallRng::seed_from_u64(7);
+        for playout in [mcts::Playout::Random, mcts::Playout::Heuristic { epsilon: 0.1 }] {
+            let config = mcts::Config { playout, budget: mcts::Budget::Iterations(2_000), ..mcts::Config::default() };
+            let statistics = mcts::search(&mut Strategy::default(), &board, &config, &mut rng);
+            let best = statistics.iter().max_by_key(|s| s.visits).unwrap();
+            assert_eq!(*best.min_max_move.index(), 2);
+            assert_eq!(best.win_rate, 1.0);
+            assert_eq!(statistics.iter().map(|s| s.visits).sum::<u32>(), 2_000);
+
+            let scored_moves = mcts::score_possible_moves(&mut Strategy::default(), &board, &config, &mut rng);
+            assert_eq!(scored_moves.iter().max_by_key(|m| m.score).map(|m| *m.min_max_move.index()), Some(2));
+        }
+    }
+
+    #[test]
+    fn empty_board_search_algorithms() {
+        let board = GameBoard::empty();
+        for algorithm in [Algorithm::PrincipalVariation, Algorithm::Mtdf] {
+            let scored_moves = score_possible_moves_with(&mut Strategy::default(), &board, u8::MAX, SearchConfig::new(algorithm));
+            assert!(scored_moves.iter().all(|m| m.score == 0), "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn possible_moves_dedupe_rotations() {
+        use crate::ttt::CellState::*;
+        // only symmetric under a half turn
+        let board = GameBoard::new(&[X, O, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY, O, X], Player::Min);
+        let moves = Strategy::possible_moves(&board).into_iter().map(|m| *m.index()).collect::<HashSet<_>>();
+        assert_eq!(moves, HashSet::from([2, 3, 4]));
+    }
+
+    #[test]
+    fn four_by_four_finds_winning_move() {
+        use crate::ttt::CellState::*;
+        let board = GameBoardMxN::<4, 4, 4>::new(&[
+            X, X, X, EMPTY,
+            O, O, O, EMPTY,
+            X, O, X, O,
+            O, X, O, X,
+        ], Player::Min);
+        assert_eq!(board.status(), BoardStatus::Ongoing);
+
+        let scored_moves = score_possible_moves(&mut StrategyMxN::<4, 4, 4>::default(), &board, 2);
+        let best = scored_moves.iter().max_by_key(|m| m.score).unwrap();
+        assert_eq!(*best.min_max_move.index(), 3);
+        assert!(best.score > 0);
+    }
+
+    #[test]
+    fn gomoku_status() {
+        use crate::ttt::CellState::*;
+        let mut board = GameBoardMxN::<5, 5, 4>::empty();
+        for index in [1, 7, 13] {
+            board.set_cell(index, X);
+        }
+        assert_eq!(board.status(), BoardStatus::Ongoing);
+        board.set_cell(19, X);
+        assert_eq!(board.status(), BoardStatus::MaxWon);
+        assert_eq!(board.winning_indices().map(Iterator::collect::<Vec<_>>), Some(vec![1, 7, 13, 19]));
+        // an earlier line takes over, breaking it brings back the first one
+        for index in [0, 5, 10] {
+            board.set_cell(index, X);
+        }
+        board.set_cell(15, X);
+        assert_eq!(board.winning_indices().map(Iterator::collect::<Vec<_>>), Some(vec![0, 5, 10, 15]));
+        board.set_cell(5, O);
+        assert_eq!(board.winning_indices().map(Iterator::collect::<Vec<_>>), Some(vec![1, 7, 13, 19]));
+        assert_eq!(board, GameBoardMxN::<5, 5, 4>::new(board.cells(), board.last_player));
+        board.set_cell(13, O);
+        assert_eq!(board.status(), BoardStatus::Ongoing);
+
+        let mut board = GameBoardMxN::<5, 5, 4>::empty();
+        for index in [9, 13, 17, 21] {
+            board.set_cell(index, O);
+        }
+        assert_eq!(board.status(), BoardStatus::MinWon);
+    }
+
+    #[test]
+    fn controller_hint_shows_winning_cell() {
+        use crate::game_controller::GameController;
+        use crate::ttt::CellState::*;
+
+        let mut game = TicTacToe::new(SmallRng::seed_from_u64(0), u8::MAX);
+        // O, the human, completes the middle row with 6
+        let state = GameBoard::new(&[X, X, EMPTY, O, O, EMPTY, X, EMPTY, EMPTY], Player::Max);
+        let hint = game.hint(&state);
+        assert_eq!(hint.lines().nth(1).unwrap(), "  .,   ., 255*");
+    }
+
+    #[test]
+    fn controller_rejects_taken_cell() {
+        use crate::common::CellIndex;
+        use crate::game_controller::{GameController, State, Status};
+        use crate::ttt::CellState::*;
+
+        let mut game = TicTacToe::new(SmallRng::seed_from_u64(0), u8::MAX);
+        assert_eq!("5".parse::<CellIndex>(), Ok(CellIndex(4)));
+        assert!("0".parse::<CellIndex>().is_err());
+        assert!("x".parse::<CellIndex>().is_err());
+
+        let initial = game.initial();
+        assert_eq!(game.do_move(&initial, CellIndex(9)), Err("Cell 10 does not exist".to_string()));
+        let state = game.do_move(&initial, CellIndex(4)).unwrap();
+        assert!(game.do_move(&state, CellIndex(4)).is_err());
+
+        // O threatens to complete the middle row, so the computer has to block
+        let state = GameBoard::new(&[X, EMPTY, EMPTY, O, O, EMPTY, EMPTY, EMPTY, EMPTY], Player::Max);
+        let state = game.do_move(&state, CellIndex(8)).unwrap();
+        let (state, CellIndex(index)) = game.do_computer_move(&state);
+        assert_eq!(index, 5);
+        assert_eq!(state.cells()[5], X);
+        assert_eq!(State::status(&state), Status::Playing);
+    }
+
+    #[test]
+    fn controller_searches_with_its_cache() {
+        use crate::common::CellIndex;
+        use crate::game_controller::GameController;
+        use crate::min_max::cache::HashMapCache;
+        use crate::ttt::CellState::*;
+
+        let mut game = TicTacToe::with_cache(SmallRng::seed_from_u64(0), HashMapCache::default(), u8::MAX);
+        let state = GameBoard::new(&[X, EMPTY, EMPTY, O, O, EMPTY, EMPTY, EMPTY, EMPTY], Player::Max);
+        let state = game.do_move(&state, CellIndex(8)).unwrap();
+        assert_eq!(game.do_computer_move(&state).1, CellIndex(5));
+    }
+
+    #[test]
+    fn try_do_move_rejects_illegal_moves() {
+        use crate::min_max::symmetry::{GridSymmetry, SymmetricMove};
+        use crate::ttt::{CellState::*, MoveError};
+        let at = |index| SymmetricMove(index, GridSymmetry::none());
+        let mut strategy = Strategy::default();
+        let board = GameBoard::new(&[X, EMPTY, EMPTY, O, EMPTY, EMPTY, EMPTY, EMPTY, EMPTY], Player::Min);
+        assert_eq!(strategy.try_do_move(&board, &at(3), Player::Max), Err(MoveError::CellTaken(3)));
+        assert_eq!(strategy.try_do_move(&board, &at(9), Player::Max), Err(MoveError::OutOfBounds(9)));
+        assert_eq!(strategy.try_do_move(&board, &at(4), Player::Max).map(|b| b.cells()[4]), Ok(X));
+
+        let won = GameBoard::new(&[X, X, X, O, O, EMPTY, EMPTY, EMPTY, EMPTY], Player::Max);
+        assert_eq!(strategy.try_do_move(&won, &at(5), Player::Min), Err(MoveError::GameOver));
+    }
+}
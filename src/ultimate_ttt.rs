@@ -1,10 +1,17 @@
-use crate::{min_max, ttt};
-use crate::common::{Board, Cell};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use rand::prelude::*;
+use crate::{fen, game_controller, min_max, ttt};
+use crate::common::{Board, Cell, CellIndex};
+use crate::fen::Fen;
+use crate::game_controller::{GameController, Replay, Status};
 use crate::iter_util::IterUtil;
-use crate::min_max::{Player};
-use crate::min_max::cache::{Cache, NullCache};
+use crate::min_max::{alpha_beta, Player, Strategy as _};
+use crate::min_max::cache::{Cache, NullCache, ReplacementPolicy, TranspositionTable};
 use crate::min_max::stats::SimpleStats;
-use crate::min_max::symmetry::{GridSymmetry3x3, SymmetricMove, SymmetricMove3x3, Symmetry};
+use crate::min_max::symmetry::{CanonicalState, GridSymmetry3x3, GridSymmetryAxis, GridSymmetryAxisContext, GridSymmetryAxisContext3x3, SymmetricMove, SymmetricMove3x3, Symmetry};
+use crate::min_max::zobrist;
+use crate::min_max::zobrist::ZobristHash;
 
 pub type BoardStatus = ttt::BoardStatus;
 pub type CellState = ttt::CellState;
@@ -15,6 +22,27 @@ pub struct Move {
     ttt_move: SymmetricMove3x3,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    OutOfBounds { board: usize, cell: usize },
+    WrongBoard { forced_board: usize },
+    BoardDecided(usize),
+    CellTaken { board: usize, cell: usize },
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "The game is already over"),
+            MoveError::OutOfBounds { board, cell } => write!(f, "Cell {} of board {} does not exist", cell + 1, board + 1),
+            MoveError::WrongBoard { forced_board } => write!(f, "Board {} has to be played", forced_board + 1),
+            MoveError::BoardDecided(board) => write!(f, "Board {} is already decided", board + 1),
+            MoveError::CellTaken { board, cell } => write!(f, "Cell {} of board {} is already taken", cell + 1, board + 1),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
 pub struct SubBoard {
     cells: [CellState; 9],
@@ -23,18 +51,33 @@ pub struct SubBoard {
 
 impl SubBoard {
     pub fn new(cells: [CellState; 9]) -> Self {
-        let status = ttt::GameBoard::new(cells, Player::Max).status();
+        let status = ttt::GameBoard::new(&cells, Player::Max).status();
         Self { cells, status }
     }
 }
 
-impl Cell for SubBoard {
-    fn empty() -> Self {
+impl SubBoard {
+    pub fn empty() -> Self {
         Self {
             cells: [CellState::EMPTY; 9],
             status: BoardStatus::Ongoing,
         }
     }
+
+    /// Decided boards are replaced by a canonical one, only their status matters for the rest of the game.
+    pub fn from_ttt_board(ttt_board: &ttt::GameBoard) -> Self {
+        match ttt_board.status() {
+            BoardStatus::MaxWon => CANONICAL_MAX_WIN_SUB_BOARD,
+            BoardStatus::MinWon => CANONICAL_MIN_WIN_SUB_BOARD,
+            BoardStatus::Draw => CANONICAL_DRAW_SUB_BOARD,
+            BoardStatus::Ongoing => SubBoard { cells: ttt_board.cells().try_into().expect("a 3x3 board has 9 cells"), status: BoardStatus::Ongoing },
+        }
+    }
+
+    // contribution of the sub board at `index` to the Zobrist hash of the game board
+    fn zobrist_hash(&self, index: usize) -> u64 {
+        self.cells.iter().enumerate().fold(0, |hash, (cell_index, cell)| hash ^ zobrist::key(index * 9 + cell_index, cell.ordinal()))
+    }
 }
 
 const CANONICAL_MAX_WIN_SUB_BOARD: SubBoard = SubBoard {
@@ -63,6 +106,8 @@ pub struct GameBoard {
     pub last_move: Option<(u8, u8)>,
     // (board, cell)
     status: BoardStatus,
+    // Zobrist hash of the sub boards
+    hash: u64,
 }
 
 
@@ -81,16 +126,13 @@ impl Eq for SymmetricEq {}
 
 impl GameBoard {
     pub fn ttt_board(&self, index: usize) -> ttt::GameBoard {
-        return ttt::GameBoard::new(self.sub_boards[index].cells, self.last_player);
+        return ttt::GameBoard::new(&self.sub_boards[index].cells, self.last_player);
     }
 
     pub fn update_ttt_board(&mut self, index: usize, ttt_board: ttt::GameBoard) {
-        self.sub_boards[index] = match ttt_board.status() {
-            BoardStatus::MaxWon => CANONICAL_MAX_WIN_SUB_BOARD,
-            BoardStatus::MinWon => CANONICAL_MIN_WIN_SUB_BOARD,
-            BoardStatus::Draw => CANONICAL_DRAW_SUB_BOARD,
-            BoardStatus::Ongoing => SubBoard { cells: ttt_board.cells, status: BoardStatus::Ongoing },
-        };
+        let sub_board = SubBoard::from_ttt_board(&ttt_board);
+        self.hash ^= self.sub_boards[index].zobrist_hash(index) ^ sub_board.zobrist_hash(index);
+        self.sub_boards[index] = sub_board;
     }
 
     pub fn symmetry(&self) -> GridSymmetry3x3 {
@@ -98,15 +140,61 @@ impl GameBoard {
     }
 
     pub fn empty() -> Self {
-        Self {
-            sub_boards: [SubBoard::empty(); 9],
-            last_player: Player::Max,
-            last_move: None,
-            status: BoardStatus::Ongoing,
+        Self::new([SubBoard::empty(); 9], Player::Max, None)
+    }
+
+    pub fn new(sub_boards: [SubBoard; 9], last_player: Player, last_move: Option<(u8, u8)>) -> Self {
+        let hash = sub_boards.iter().enumerate().fold(0, |hash, (index, sub_board)| hash ^ sub_board.zobrist_hash(index));
+        let status = calculate_status(&sub_boards, last_player);
+        Self { sub_boards, last_player, last_move, status, hash }
+    }
+}
+
+impl CanonicalState for GameBoard {
+    /// The image of the board under the symmetry that yields the smallest cells, ordered by [Cell::ordinal], and then
+    /// the smallest last move. Decided sub boards keep their canonical cells.
+    fn canonical_state(&self) -> Self {
+        let image = |axis: Option<GridSymmetryAxis>| {
+            let transform = |index: usize| axis.map_or(index, |axis| GridSymmetryAxisContext3x3::transform(axis, index));
+            let mut sub_boards = self.sub_boards;
+            for (index, sub_board) in self.sub_boards.iter().enumerate() {
+                let mut cells = sub_board.cells;
+                if sub_board.status == BoardStatus::Ongoing {
+                    for (cell_index, cell) in sub_board.cells.iter().enumerate() {
+                        cells[transform(cell_index)] = *cell;
+                    }
+                }
+                sub_boards[transform(index)] = SubBoard { cells, ..*sub_board };
+            }
+            let last_move = self.last_move.map(|(board, cell)| (transform(board.into()) as u8, transform(cell.into()) as u8));
+            (sub_boards, last_move)
+        };
+        let key = |(sub_boards, last_move): &([SubBoard; 9], Option<(u8, u8)>)| {
+            (sub_boards.iter().flat_map(|sub_board| sub_board.cells.map(|cell| cell.ordinal())).collect::<Vec<_>>(), *last_move)
+        };
+        let (sub_boards, last_move) = std::iter::once(None)
+            .chain(GridSymmetryAxisContext3x3::axes().iter().map(Some))
+            .map(image)
+            .min_by_key(key)
+            .expect("the identity is always an image");
+        if sub_boards == self.sub_boards && last_move == self.last_move {
+            self.clone()
+        } else {
+            Self::new(sub_boards, self.last_player, last_move)
         }
     }
 }
 
+// the board the next player is sent to is encoded as an extra component after the 81 cells
+const FORCED_BOARD_COMPONENT: usize = 81;
+
+impl ZobristHash for GameBoard {
+    fn zobrist_hash(&self) -> u64 {
+        let forced_board = self.last_move.map_or(9, |(_, cell)| usize::from(cell));
+        self.hash ^ zobrist::key(FORCED_BOARD_COMPONENT, forced_board) ^ zobrist::player_key(self.last_player)
+    }
+}
+
 fn calculate_status(sub_boards: &[SubBoard; 9], last_player: Player) -> BoardStatus {
     let statuses = sub_boards.map(|board| board.status);
     let cells = statuses
@@ -118,7 +206,7 @@ fn calculate_status(sub_boards: &[SubBoard; 9], last_player: Player) -> BoardSta
             }
         });
 
-    let overall_ttt_board = ttt::GameBoard::new(cells, last_player);
+    let overall_ttt_board = ttt::GameBoard::new(&cells, last_player);
     match overall_ttt_board.status() {
         BoardStatus::Ongoing => {
             if statuses.iter().any(|status| status == &BoardStatus::Ongoing) {
@@ -152,25 +240,149 @@ impl Board for GameBoard {
     }
 }
 
-pub struct Strategy<CACHE: Cache<GameBoard>> {
+/// Scores the positions of the search that are still ongoing.
+pub trait Evaluator {
+    /// The score of a won game for the winner, the loser gets its negation.
+    fn win_score(&self) -> i32;
+
+    /// Scores the ongoing `board` from the view of `player`.
+    fn evaluate(&self, board: &GameBoard, player: Player) -> i32;
+}
+
+/// Counts the won sub boards of a player minus those of the opponent. A won game scores 1 like a single sub board.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DefaultEvaluator;
+
+impl Evaluator for DefaultEvaluator {
+    fn win_score(&self) -> i32 {
+        1
+    }
+
+    fn evaluate(&self, board: &GameBoard, player: Player) -> i32 {
+        board.sub_boards.map(|board| ttt::Strategy::score_board_state(board.status, player)).iter().sum()
+    }
+}
+
+/// The weights of the features of [WeightedEvaluator]. Every feature is counted for a player minus for the opponent.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Weights {
+    /// Won sub boards.
+    pub sub_boards: i32,
+    /// Lines of an ongoing sub board with two marks and an empty cell.
+    pub local_threats: i32,
+    /// Lines of the whole board with two won sub boards and an ongoing one.
+    pub global_threats: i32,
+    /// Marks in the middle cell of the ongoing sub boards and in the ongoing middle sub board.
+    pub centre: i32,
+    /// The side to move may choose its sub board, because the opponent sent it to a decided one.
+    pub free_choice: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self { sub_boards: 100, local_threats: 10, global_threats: 50, centre: 5, free_choice: 20 }
+    }
+}
+
+/// Sums up features of the position multiplied by their [Weights].
+#[derive(Debug, Copy, Clone, Default)]
+pub struct WeightedEvaluator {
+    pub weights: Weights,
+}
+
+impl WeightedEvaluator {
+    pub fn new(weights: Weights) -> Self {
+        Self { weights }
+    }
+
+    // the weighted features of `player` alone, except for the free choice
+    fn features(&self, board: &GameBoard, player: Player) -> i32 {
+        let weights = &self.weights;
+        let mark = CellState::from(player);
+        let won = if player == Player::Max { BoardStatus::MaxWon } else { BoardStatus::MinWon };
+        let statuses = board.sub_boards.map(|sub_board| sub_board.status);
+        let mut score = weights.global_threats * threats(&statuses, won, BoardStatus::Ongoing);
+        for (index, sub_board) in board.sub_boards.iter().enumerate() {
+            if sub_board.status == won {
+                score += weights.sub_boards;
+            } else if sub_board.status == BoardStatus::Ongoing {
+                score += weights.local_threats * threats(&sub_board.cells, mark, CellState::EMPTY);
+                let marks = if index == CENTRE { sub_board.cells.iter().filter(|cell| **cell == mark).count() } else { 0 };
+                score += weights.centre * (marks + usize::from(sub_board.cells[CENTRE] == mark)) as i32;
+            }
+        }
+        score
+    }
+}
+
+impl Evaluator for WeightedEvaluator {
+    fn win_score(&self) -> i32 {
+        1_000_000
+    }
+
+    fn evaluate(&self, board: &GameBoard, player: Player) -> i32 {
+        let sent_to_decided_board = board.last_move.is_some() && board.forced_board().is_none();
+        let free_choice = if !sent_to_decided_board {
+            0
+        } else if player == board.last_player {
+            -self.weights.free_choice
+        } else {
+            self.weights.free_choice
+        };
+        self.features(board, player) - self.features(board, !player) + free_choice
+    }
+}
+
+// the index of the middle cell or sub board
+const CENTRE: usize = 4;
+
+// the rows, columns and diagonals of a 3x3 grid
+const LINES: [[usize; 3]; 8] = [[0, 1, 2], [3, 4, 5], [6, 7, 8], [0, 3, 6], [1, 4, 7], [2, 5, 8], [0, 4, 8], [2, 4, 6]];
+
+// the lines with two `own` cells and an `open` one
+fn threats<T: Copy + Eq>(cells: &[T; 9], own: T, open: T) -> i32 {
+    LINES.iter()
+        .filter(|line| {
+            line.iter().filter(|index| cells[**index] == own).count() == 2 && line.iter().any(|index| cells[*index] == open)
+        })
+        .count() as i32
+}
+
+pub struct Strategy<CACHE: Cache<GameBoard>, EVALUATOR: Evaluator = DefaultEvaluator> {
     ttt_strategy: ttt::Strategy,
     cache: CACHE,
+    evaluator: EVALUATOR,
     pub stats: SimpleStats,
 }
 
 impl<CACHE: Cache<GameBoard>> Strategy<CACHE> {
     pub fn new(cache: CACHE) -> Self {
+        Self::with_evaluator(cache, DefaultEvaluator)
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> Strategy<CACHE, WeightedEvaluator> {
+    /// A strategy with the [WeightedEvaluator] of the default weights.
+    pub fn weighted(cache: CACHE) -> Self {
+        Self::with_evaluator(cache, WeightedEvaluator::default())
+    }
+}
+
+impl<CACHE: Cache<GameBoard>, EVALUATOR: Evaluator> Strategy<CACHE, EVALUATOR> {
+    pub fn with_evaluator(cache: CACHE, evaluator: EVALUATOR) -> Self {
         Self {
             ttt_strategy: ttt::Strategy::new(NullCache::default()),
             cache,
+            evaluator,
             stats: SimpleStats::default(),
         }
     }
 }
 
-impl<CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
+impl<CACHE: Cache<GameBoard>, EVALUATOR: Evaluator> min_max::Strategy for Strategy<CACHE, EVALUATOR> {
     type State = GameBoard;
     type Move = Move;
+    type MoveError = MoveError;
     type Cache = CACHE;
     type Stats = SimpleStats;
     
@@ -181,6 +393,8 @@ impl<CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
             canonical_index
         });
         match canonical_forced_board_index {
+            // nothing can be played once the game is decided, even if some sub boards are still open
+            _ if state.status != BoardStatus::Ongoing => Box::new(std::iter::empty()),
             Some(board_index) if state.sub_boards[board_index].status == BoardStatus::Ongoing => {
                 Box::new(ttt::Strategy::possible_moves(&state.ttt_board(board_index)).into_iter()
                     .map(move |ttt_move| Move {
@@ -229,26 +443,48 @@ impl<CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
         new_state
     }
 
+    fn try_do_move(&mut self, state: &GameBoard, ultimate_move: &Move, player: Player) -> Result<GameBoard, MoveError> {
+        let (board, cell) = (*ultimate_move.ttt_board.index(), *ultimate_move.ttt_move.index());
+        if state.status != BoardStatus::Ongoing {
+            return Err(MoveError::GameOver);
+        }
+        if board >= 9 || cell >= 9 {
+            return Err(MoveError::OutOfBounds { board, cell });
+        }
+        if let Some(forced_board) = state.forced_board() {
+            if board != forced_board {
+                return Err(MoveError::WrongBoard { forced_board });
+            }
+        }
+        let sub_board = &state.sub_boards[board];
+        if sub_board.status != BoardStatus::Ongoing {
+            Err(MoveError::BoardDecided(board))
+        } else if sub_board.cells[cell] != CellState::EMPTY {
+            Err(MoveError::CellTaken { board, cell })
+        } else {
+            Ok(self.do_move(state, ultimate_move, player))
+        }
+    }
+
     fn score(&mut self, state: &GameBoard, player: Player) -> i32 {
+        let win_score = self.evaluator.win_score();
         match state.status() {
             BoardStatus::MaxWon => {
                 if player == Player::Max {
-                    1
+                    win_score
                 } else {
-                    -1
+                    -win_score
                 }
             }
             BoardStatus::MinWon => {
                 if player == Player::Min {
-                    1
+                    win_score
                 } else {
-                    -1
+                    -win_score
                 }
             }
             BoardStatus::Draw => 0,
-            BoardStatus::Ongoing => {
-                state.sub_boards.map(|board| ttt::Strategy::score_board_state(board.status, player)).iter().sum()
-            }
+            BoardStatus::Ongoing => self.evaluator.evaluate(state, player),
         }
     }
 
@@ -262,12 +498,262 @@ impl<CACHE: Cache<GameBoard>> min_max::Strategy for Strategy<CACHE> {
     }
 }
 
+impl GameBoard {
+    /// The sub board the next move has to be played in, `None` if the player is free to choose.
+    pub fn forced_board(&self) -> Option<usize> {
+        self.last_move
+            .map(|(_, cell)| usize::from(cell))
+            .filter(|board| self.sub_boards[*board].status == BoardStatus::Ongoing)
+    }
+}
+
+impl Display for GameBoard {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for board_row in 0..3 {
+            if board_row > 0 {
+                writeln!(f, "------+-------+------")?;
+            }
+            for cell_row in 0..3 {
+                for board_column in 0..3 {
+                    if board_column > 0 {
+                        write!(f, "| ")?;
+                    }
+                    let sub_board = &self.sub_boards[board_row * 3 + board_column];
+                    for cell_column in 0..3 {
+                        // drawn sub boards are replaced by a canonical one, so their cells mean nothing
+                        match (sub_board.status, sub_board.cells[cell_row * 3 + cell_column]) {
+                            (BoardStatus::Draw, _) => write!(f, "- ")?,
+                            (_, CellState::EMPTY) => write!(f, ". ")?,
+                            (_, cell) => write!(f, "{} ", cell)?,
+                        }
+                    }
+                }
+                writeln!(f)?;
+            }
+        }
+        if self.status == BoardStatus::Ongoing {
+            match self.forced_board() {
+                Some(board) => writeln!(f, "Next board: {}", board + 1)?,
+                None => writeln!(f, "Next board: any")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The cells are the 9 rows of the whole grid, followed by the side to move and the last move as `board,cell`, or `-`
+/// before the first move, e.g. `9/9/9/9/4o4/9/9/9/9 x 5,5`. Decided sub boards are written in their canonical form.
+impl Display for Fen<GameBoard> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let board = &self.0;
+        let rows = (0..9).map(|row| (0..9).map(move |column| {
+            let sub_board = &board.sub_boards[row / 3 * 3 + column / 3];
+            sub_board.cells[row % 3 * 3 + column % 3].symbol()
+        }));
+        fen::write_rows(f, rows)?;
+        write!(f, " {} ", fen::side_symbol(!board.last_player))?;
+        match board.last_move {
+            Some((board, cell)) => write!(f, "{}", Position { board: usize::from(board), cell: usize::from(cell) }),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for Fen<GameBoard> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [rows, side, last_move] = fen::fields(s)?;
+        let rows = fen::parse_rows(rows, 9, 9)?;
+        let symbol_at = |board: usize, cell: usize| rows[board / 3 * 3 + cell / 3][board % 3 * 3 + cell % 3];
+        let mut sub_boards = [SubBoard::empty(); 9];
+        for (index, sub_board) in sub_boards.iter_mut().enumerate() {
+            let mut cells = [CellState::EMPTY; 9];
+            for (cell_index, cell) in cells.iter_mut().enumerate() {
+                if let Some(symbol) = symbol_at(index, cell_index) {
+                    *cell = CellState::from_symbol(symbol).ok_or_else(|| format!("Invalid cell {}", symbol))?;
+                }
+            }
+            *sub_board = SubBoard::from_ttt_board(&ttt::GameBoard::new(&cells, Player::Max));
+        }
+        let last_player = !fen::parse_side(side)?;
+        let last_move = match last_move {
+            "-" => None,
+            _ => {
+                let Position { board, cell } = last_move.parse()?;
+                if symbol_at(board, cell) != CellState::from(last_player).symbol() {
+                    return Err(format!("The last move {} has to be a cell of the player who moved last", last_move));
+                }
+                Some((board as u8, cell as u8))
+            }
+        };
+        Ok(Fen(GameBoard::new(sub_boards, last_player, last_move)))
+    }
+}
+
+impl game_controller::State for GameBoard {
+    fn player(&self) -> game_controller::Player {
+        match self.last_player {
+            Player::Max => game_controller::Player::Human,
+            Player::Min => game_controller::Player::Computer,
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self.status {
+            BoardStatus::MaxWon => Status::Done { winner: game_controller::Player::Computer },
+            BoardStatus::MinWon => Status::Done { winner: game_controller::Player::Human },
+            BoardStatus::Draw => Status::Draw,
+            BoardStatus::Ongoing => Status::Playing,
+        }
+    }
+}
+
+/// A move entered by the human player as `board,cell`, both counted from 1 row by row from the top left.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Position {
+    pub board: usize,
+    pub cell: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.board + 1, self.cell + 1)
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (board, cell) = s.split_once(',')
+            .ok_or_else(|| format!("Invalid move {}, moves are given as board,cell e.g. 4,7", s))?;
+        // the cell index fits any grid, the grids of ultimate-ttt all have 9 cells
+        let index = |part: &str| match part.trim().parse()? {
+            CellIndex(index) if index < 9 => Ok(index),
+            _ => Err(format!("Invalid move {}, boards and cells are 1 to 9", s)),
+        };
+        Ok(Position { board: index(board)?, cell: index(cell)? })
+    }
+}
+
+impl game_controller::Move for Position {}
+
+impl Position {
+    fn to_move(self) -> Move {
+        Move {
+            ttt_board: SymmetricMove(self.board, GridSymmetry3x3::none()),
+            ttt_move: SymmetricMove(self.cell, GridSymmetry3x3::none()),
+        }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>, EVALUATOR: Evaluator> Replay for Strategy<CACHE, EVALUATOR> {
+    type Notation = Position;
+
+    fn initial() -> GameBoard {
+        GameBoard::empty()
+    }
+
+    fn play(&mut self, state: &GameBoard, position: Position, player: Player) -> Result<GameBoard, String> {
+        self.try_do_move(state, &position.to_move(), player).map_err(|e| e.to_string())
+    }
+
+    fn notations(state: &GameBoard, m: &Move) -> Vec<Position> {
+        let (board, cell) = (*m.ttt_board.index(), *m.ttt_move.index());
+        // The search plays the forced board in its canonical form, which may be another sub board that only
+        // equals the forced one under symmetry. The cell is moved along to the board that really has to be played.
+        let position = match state.forced_board() {
+            Some(forced_board) if forced_board != board => Position {
+                board: forced_board,
+                cell: transfer_cell(state, board, forced_board, cell),
+            },
+            _ => Position { board, cell },
+        };
+        vec![position]
+    }
+}
+
+/// The human plays [Player::Min] and moves first, the computer searches `depth` moves ahead.
+pub struct UltimateTicTacToe<CACHE: Cache<GameBoard> = TranspositionTable> {
+    rng: SmallRng,
+    strategy: Strategy<CACHE>,
+    depth: u8,
+}
+
+impl UltimateTicTacToe {
+    pub fn new(rng: SmallRng, depth: u8) -> Self {
+        Self::with_cache(rng, TranspositionTable::with_capacity_mb(64, ReplacementPolicy::DepthPreferred), depth)
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> UltimateTicTacToe<CACHE> {
+    pub fn with_cache(rng: SmallRng, cache: CACHE, depth: u8) -> Self {
+        Self { rng, strategy: Strategy::new(cache), depth }
+    }
+}
+
+impl<CACHE: Cache<GameBoard>> GameController for UltimateTicTacToe<CACHE> {
+    type State = GameBoard;
+    type Move = Position;
+
+    fn initial(&mut self) -> Self::State {
+        Strategy::<CACHE>::initial()
+    }
+
+    fn do_move(&mut self, state: &Self::State, position: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, position, Player::Min)
+    }
+
+    fn do_computer_move(&mut self, state: &Self::State) -> (Self::State, Self::Move) {
+        let moves = alpha_beta(&mut self.strategy, &mut state.clone(), self.depth);
+        let best = moves.choose(&mut self.rng).expect("the computer only moves while the game is ongoing");
+        let position = *Strategy::<CACHE>::notations(state, &best.min_max_move).choose(&mut self.rng).expect("a move has a notation");
+        let state = self.strategy.play(state, position, Player::Max).expect("the search only finds legal moves");
+        (state, position)
+    }
+
+    fn play(&mut self, state: &Self::State, m: Self::Move) -> Result<Self::State, String> {
+        self.strategy.play(state, m, game_controller::State::player(state).into())
+    }
+
+    fn hint(&mut self, state: &Self::State) -> String {
+        let mut scored_positions = game_controller::score_notations(&mut self.strategy, state, self.depth, Player::Min);
+        scored_positions.sort_by_key(|(position, _)| (position.board, position.cell));
+        game_controller::format_hint_list(&scored_positions)
+    }
+}
+
+// The index `cell` of sub board `from` is moved to by a symmetry that maps `from` onto `to`. The symmetries of the whole
+// board come first, as the moved cell has to send the opponent to the board that is equivalent to the one the search
+// sent them to. The sub boards are only compared up to their own symmetry there, so a symmetry of the sub boards alone
+// is the fallback.
+fn transfer_cell(state: &GameBoard, from: usize, to: usize, cell: usize) -> usize {
+    let (from_cells, to_cells) = (&state.sub_boards[from].cells, &state.sub_boards[to].cells);
+    let maps_cells = |axis: &GridSymmetryAxis| {
+        (0..9).all(|index| from_cells[index] == to_cells[GridSymmetryAxisContext3x3::transform(*axis, index)])
+    };
+    let global = state.symmetry().axes().iter()
+        .filter(|axis| GridSymmetryAxisContext3x3::transform(*axis, from) == to)
+        .find(maps_cells);
+    match global {
+        Some(axis) => GridSymmetryAxisContext3x3::transform(axis, cell),
+        None if from_cells == to_cells => cell,
+        None => GridSymmetryAxisContext3x3::axes().iter()
+            .find(maps_cells)
+            .map(|axis| GridSymmetryAxisContext3x3::transform(axis, cell))
+            .expect("the sub boards are equal under symmetry"),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use std::time::Instant;
+    use std::time::{Duration, Instant};
     use ahash::HashSet;
     use itertools::Itertools;
-    use crate::min_max::{score_possible_moves, Strategy as _};
+    use crate::min_max::{alpha_beta, mtdf, score_best_moves, score_possible_lines, score_possible_moves, score_possible_moves_with, Algorithm, CacheFlag, ScoredMove, SearchBudget, SearchConfig, Strategy as _};
+    use crate::min_max::cache::{HashMapCache, ReplacementPolicy, SymmetricCache, TranspositionTable};
+    use crate::min_max::deepening::iterative_deepening;
     use crate::ttt::CellState::{EMPTY as E, O, X};
     use super::*;
 
@@ -320,6 +806,21 @@ mod test {
         ], Player::Min), BoardStatus::Draw);
     }
 
+    #[test]
+    fn mirror_images_share_canonical_state() {
+        let mut strategy = Strategy::new(HashMapCache::default());
+        let mut play = |moves: &[(usize, usize)]| moves.iter().zip([Player::Min, Player::Max].into_iter().cycle())
+            .fold(GameBoard::empty(), |state, (&(board, cell), player)| strategy.play(&state, Position { board, cell }, player).unwrap());
+        // mirrored at the vertical axis, which swaps the left and right columns of the boards and their cells
+        let (state, mirrored) = (play(&[(4, 0), (0, 4), (4, 8)]), play(&[(4, 2), (2, 4), (4, 6)]));
+        assert_ne!(state, mirrored);
+        assert_eq!(state.canonical_state(), mirrored.canonical_state());
+
+        let scores = |scored_moves: Vec<ScoredMove<Move>>| scored_moves.into_iter().map(|m| m.score).collect_vec();
+        let symmetric = score_possible_moves(&mut Strategy::new(SymmetricCache::new(HashMapCache::default())), &state, 5);
+        assert_eq!(scores(symmetric), scores(score_possible_moves(&mut Strategy::new(HashMapCache::default()), &state, 5)));
+    }
+
     #[test]
     fn first_possible_moves() {
         let board = GameBoard::empty();
@@ -338,22 +839,7 @@ mod test {
 
     #[test]
     fn first_move() {
-        let empty = SubBoard::new([
-            E, E, E,
-            E, E, E,
-            E, E, E
-        ]);
-
-        let board = GameBoard {
-            sub_boards: [
-                empty, empty, empty,
-                empty, empty, empty,
-                empty, empty, empty,
-            ],
-            last_player: Player::Max,
-            last_move: None,
-            status: BoardStatus::Ongoing,
-        };
+        let Fen(board) = "9/9/9/9/9/9/9/9/9 o -".parse::<Fen<GameBoard>>().unwrap();
 
         let start = Instant::now();
         let scored_moves = score_possible_moves(&mut Strategy::new(NullCache::default()), &board, 15);
@@ -364,4 +850,282 @@ mod test {
         assert_eq!(*best_move.ttt_board.index(), 4);
         assert_eq!(*best_move.ttt_move.index(), 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn iterative_deepening_respects_budget() {
+        let board = GameBoard::empty();
+        let mut strategy = Strategy::new(HashMapCache::default());
+
+        let result = iterative_deepening(&mut strategy, &board, SearchBudget::nodes(200_000), u8::MAX).unwrap();
+        assert!(result.depth >= 3);
+        assert_eq!(result.scored_moves.len(), 9);
+        assert!(result.nodes <= 200_001);
+
+        // the deadline has already passed when it is first checked, so the search stops within the first nodes
+        let result = iterative_deepening(&mut Strategy::new(HashMapCache::default()), &board, SearchBudget::time(Duration::ZERO), u8::MAX).unwrap();
+        assert!(result.depth >= 1);
+        assert!(result.nodes <= 1024, "{}", result.nodes);
+    }
+
+    #[test]
+    fn iterative_deepening_matches_fixed_depth() {
+        let board = GameBoard::empty();
+        let expected = score_possible_moves(&mut Strategy::new(NullCache), &board, 5);
+        let result = iterative_deepening(&mut Strategy::new(NullCache), &board, SearchBudget::unlimited(), 5).unwrap();
+
+        assert_eq!(result.depth, 5);
+        for m in &result.scored_moves {
+            let fixed = expected.iter()
+                .find(|e| e.min_max_move.ttt_board.index() == m.min_max_move.ttt_board.index() && e.min_max_move.ttt_move.index() == m.min_max_move.ttt_move.index())
+                .unwrap();
+            assert_eq!(fixed.score, m.score);
+        }
+    }
+
+    #[test]
+    fn principal_variation_reaches_horizon() {
+        let board = GameBoard::empty();
+        let lines = score_possible_lines(&mut Strategy::new(NullCache), &board, 4);
+        let scored_moves = score_possible_moves(&mut Strategy::new(NullCache), &board, 4);
+
+        assert_eq!(lines.iter().map(|l| l.score).collect_vec(), scored_moves.iter().map(|m| m.score).collect_vec());
+        for line in lines {
+            assert_eq!(line.principal_variation.len(), 3);
+        }
+    }
+
+    #[test]
+    fn search_records_stats() {
+        let mut strategy = Strategy::new(HashMapCache::default());
+        let scored_moves = score_possible_moves(&mut strategy, &GameBoard::empty(), 6);
+        println!("{}", strategy.stats);
+
+        let stats = &strategy.stats;
+        assert_eq!(stats.search_count, 1);
+        assert_eq!(stats.nodes_per_ply.len(), 6);
+        assert_eq!(stats.nodes_per_ply[0], scored_moves.len() as u64);
+        assert!(stats.cache_hit_count > 0);
+        assert!(stats.prune_count > 0);
+        assert!(stats.effective_branching_factor().unwrap() > 1.0);
+    }
+
+    #[test]
+    fn incremental_zobrist_hash() {
+        let mut strategy = Strategy::new(NullCache);
+        let mut board = GameBoard::empty();
+        let mut player = Player::Max;
+        for _ in 0..30 {
+            let Some(m) = Strategy::<NullCache>::possible_moves(&board).last() else { break };
+            board = strategy.do_move(&board, &m, player);
+            player = !player;
+            let recomputed = GameBoard::new(board.sub_boards, board.last_player, board.last_move);
+            assert_eq!(board.zobrist_hash(), recomputed.zobrist_hash());
+            assert_eq!(board, recomputed);
+        }
+    }
+
+    #[test]
+    fn transposition_table_search() {
+        let board = GameBoard::empty();
+        let table = TranspositionTable::with_capacity_mb(16, ReplacementPolicy::TwoTier);
+        let mut strategy = Strategy::new(table);
+        let scored_moves = score_possible_moves(&mut strategy, &board, 9);
+        let expected = score_possible_moves(&mut Strategy::new(HashMapCache::default()), &board, 9);
+
+        assert_eq!(scored_moves.iter().map(|m| m.score).collect_vec(), expected.iter().map(|m| m.score).collect_vec());
+        assert!(strategy.stats.cache_hit_count > 0);
+    }
+
+    #[test]
+    fn mcts_iteration_budget() {
+        use rand::SeedableRng;
+        use rand::rngs::SmallRng;
+        use crate::mcts;
+
+        let config = mcts::Config { budget: mcts::Budget::Iterations(500), ..mcts::Config::default() };
+        let statistics = mcts::search(&mut Strategy::new(NullCache), &GameBoard::empty(), &config, &mut SmallRng::seed_from_u64(1));
+        assert_eq!(statistics.len(), 9);
+        assert_eq!(statistics.iter().map(|s| s.visits).sum::<u32>(), 500);
+        assert!(statistics.iter().all(|s| s.visits > 0 && (0.0..=1.0).contains(&s.win_rate)));
+    }
+
+    #[test]
+    fn root_pruning_finds_same_best_moves() {
+        let mut board = GameBoard::empty();
+        let mut full = Strategy::new(NullCache);
+        let scored_moves = score_possible_moves(&mut full, &board, 7);
+        let mut pruned = Strategy::new(NullCache);
+        let bounded_moves = score_best_moves(&mut pruned, &board, 7);
+
+        println!("nodes with root pruning: {}, without: {}", pruned.stats.node_count(), full.stats.node_count());
+        assert!(pruned.stats.node_count() < full.stats.node_count());
+
+        let best_score = scored_moves.iter().map(|m| m.score).max().unwrap();
+        for (exact, bounded) in scored_moves.iter().zip(&bounded_moves) {
+            match bounded.flag {
+                CacheFlag::Exact => assert_eq!(exact.score, bounded.score),
+                CacheFlag::UpperBound => assert!(exact.score <= bounded.score && bounded.score < best_score),
+                CacheFlag::LowerBound => panic!("root moves are never lower bounds"),
+            }
+        }
+
+        let best_moves = alpha_beta(&mut Strategy::new(NullCache), &mut board, 7);
+        assert!(!best_moves.is_empty());
+        assert!(best_moves.iter().all(|m| m.score == best_score));
+        assert_eq!(best_moves.len(), scored_moves.iter().filter(|m| m.score == best_score).count());
+    }
+
+    // plays the given move indices (into `possible_moves`, wrapping around) alternating from Max
+    fn play(move_indices: &[usize]) -> GameBoard {
+        let mut strategy = Strategy::new(NullCache);
+        let mut board = GameBoard::empty();
+        let mut player = Player::Max;
+        for index in move_indices {
+            let moves = Strategy::<NullCache>::possible_moves(&board).collect_vec();
+            let m = moves[index % moves.len()].clone();
+            board = strategy.do_move(&board, &m, player);
+            player = !player;
+        }
+        board
+    }
+
+    #[test]
+    fn search_algorithms_agree() {
+        // Max is to move again after an even number of moves
+        let board = play(&[1, 3, 0, 5, 2, 2, 4, 1, 0, 0, 3, 1, 2, 6, 0, 4, 5, 1, 1, 2]);
+        let expected = score_possible_moves(&mut Strategy::new(NullCache), &board, 7);
+        assert!(expected.iter().any(|m| m.score != 0));
+
+        for algorithm in [Algorithm::PrincipalVariation, Algorithm::Mtdf] {
+            let mut strategy = Strategy::new(NullCache);
+            let scored_moves = score_possible_moves_with(&mut strategy, &board, 7, SearchConfig::new(algorithm));
+            assert_eq!(scored_moves.iter().map(|m| m.score).collect_vec(), expected.iter().map(|m| m.score).collect_vec(), "{:?}", algorithm);
+        }
+
+        let best_score = expected.iter().map(|m| m.score).max().unwrap();
+        assert_eq!(mtdf(&mut Strategy::new(HashMapCache::default()), &board, 7, 0), best_score);
+    }
+
+    #[test]
+    fn principal_variation_search_visits_fewer_nodes() {
+        let board = GameBoard::empty();
+        let mut alpha_beta = Strategy::new(HashMapCache::default());
+        score_possible_moves_with(&mut alpha_beta, &board, 10, SearchConfig::new(Algorithm::AlphaBeta));
+        for algorithm in [Algorithm::PrincipalVariation, Algorithm::Mtdf] {
+            let mut strategy = Strategy::new(HashMapCache::default());
+            score_possible_moves_with(&mut strategy, &board, 10, SearchConfig::new(algorithm));
+            println!("{:?}: {} nodes, alpha beta: {} nodes", algorithm, strategy.stats.node_count(), alpha_beta.stats.node_count());
+            assert!(strategy.stats.node_count() < alpha_beta.stats.node_count());
+        }
+    }
+
+    #[test]
+    fn controller_enforces_forced_board() {
+        assert_eq!("4,7".parse::<Position>(), Ok(Position { board: 3, cell: 6 }));
+        assert!("4".parse::<Position>().is_err());
+        assert!("4,10".parse::<Position>().is_err());
+
+        let mut game = UltimateTicTacToe::new(SmallRng::seed_from_u64(0), 3);
+        let initial = game.initial();
+        let state = game.do_move(&initial, Position { board: 0, cell: 4 }).unwrap();
+        assert_eq!(state.forced_board(), Some(4));
+
+        let (state, position) = game.do_computer_move(&state);
+        assert_eq!(position.board, 4);
+        assert_eq!(state.sub_boards[4].cells[position.cell], X);
+        assert_eq!(state.forced_board(), Some(position.cell));
+
+        let forced_board = position.cell;
+        let other_board = (forced_board + 1) % 9;
+        assert!(game.do_move(&state, Position { board: other_board, cell: 0 }).is_err());
+
+        let Fen(state) = "x8/9/9/9/9/9/9/9/9 o 1,1".parse::<Fen<GameBoard>>().unwrap();
+        assert!(game.do_move(&state, Position { board: 0, cell: 0 }).is_err());
+        assert!(game.do_move(&state, Position { board: 0, cell: 1 }).is_ok());
+    }
+
+    #[test]
+    fn computer_plays_forced_board_under_symmetry() {
+        // sub board 0 and 2 are mirror images, the search only looks at board 0
+        let Fen(state) = "x4o2x/9/9/9/4o4/9/9/4o4/9 x 2,3".parse::<Fen<GameBoard>>().unwrap();
+        assert_eq!(state.forced_board(), Some(2));
+
+        let mut game = UltimateTicTacToe::new(SmallRng::seed_from_u64(0), 2);
+        let (state, position) = game.do_computer_move(&state);
+        assert_eq!(position.board, 2);
+        assert_eq!(state.sub_boards[2].cells[position.cell], X);
+        assert_eq!(state.sub_boards[0], SubBoard::new([X, E, E, E, E, E, E, E, E]));
+        assert_eq!(state.forced_board(), Some(position.cell));
+
+        // the top corner boards are mirror images but also equal on their own, the cell still has to be mirrored to
+        // send the opponent to the board the search sent them to
+        let Fen(state) = "1x3o1x1/9/9/9/4o4/9/9/4o4/9 x 2,3".parse::<Fen<GameBoard>>().unwrap();
+        assert_eq!(state.forced_board(), Some(2));
+        let searched = Move {
+            ttt_board: SymmetricMove(0, state.symmetry()),
+            ttt_move: SymmetricMove(0, GridSymmetry3x3::none()),
+        };
+        assert_eq!(Strategy::<NullCache>::notations(&state, &searched), vec![Position { board: 2, cell: 2 }]);
+    }
+
+    #[test]
+    fn position_notation() {
+        assert_eq!(Fen(GameBoard::empty()).to_string(), "9/9/9/9/9/9/9/9/9 o -");
+        let mut game = UltimateTicTacToe::new(SmallRng::seed_from_u64(0), 2);
+        let mut state = game.initial();
+        for position in ["5,5", "5,1", "1,5", "5,9", "9,5", "5,3"] {
+            state = game.play(&state, position.parse().unwrap()).unwrap();
+            let notation = Fen(state.clone()).to_string();
+            assert_eq!(notation.parse(), Ok(Fen(state.clone())), "{}", notation);
+        }
+        assert_eq!(Fen(state.clone()).to_string(), "9/1o7/9/3x1x3/4o4/5x3/9/7o1/9 o 5,3");
+
+        // the won centre board is written in its canonical form, sending there frees the choice of the board
+        let Fen(won) = "9/9/9/9/3ooo3/9/9/9/9 x 5,5".parse::<Fen<GameBoard>>().unwrap();
+        assert_eq!(won.sub_boards[4], CANONICAL_MIN_WIN_SUB_BOARD);
+        assert_eq!(won.forced_board(), None);
+        assert_eq!(Fen(won).to_string(), "9/9/9/3ooo3/3ooo3/3ooo3/9/9/9 x 5,5");
+
+        assert!("9/9/9/9/9/9/9/9 o -".parse::<Fen<GameBoard>>().is_err());
+        assert!("9/9/9/9/9/9/9/9/9 o 0,1".parse::<Fen<GameBoard>>().is_err());
+        // the last move has to name a cell of the player who moved last
+        assert!("9/9/9/9/4o4/9/9/9/9 x 5,4".parse::<Fen<GameBoard>>().is_err());
+        assert!("9/9/9/9/4o4/9/9/9/9 o 5,5".parse::<Fen<GameBoard>>().is_err());
+        assert!("9/9/9/9/4z4/9/9/9/9 o -".parse::<Fen<GameBoard>>().is_err());
+    }
+
+    #[test]
+    fn weighted_features() {
+        let zero = Weights { sub_boards: 0, local_threats: 0, global_threats: 0, centre: 0, free_choice: 0 };
+
+        // max threatens the top row of the first board, min holds the centre of the centre board
+        let Fen(threat) = "xx7/9/9/9/4o4/9/9/9/9 x 5,5".parse::<Fen<GameBoard>>().unwrap();
+        assert_eq!(WeightedEvaluator::new(Weights { local_threats: 1, ..zero }).evaluate(&threat, Player::Max), 1);
+        assert_eq!(WeightedEvaluator::new(Weights { local_threats: 1, ..zero }).evaluate(&threat, Player::Min), -1);
+        assert_eq!(WeightedEvaluator::new(Weights { centre: 1, ..zero }).evaluate(&threat, Player::Max), -2);
+        assert_eq!(WeightedEvaluator::new(Weights { free_choice: 1, ..zero }).evaluate(&threat, Player::Max), 0);
+        assert_eq!(DefaultEvaluator.evaluate(&threat, Player::Max), 0);
+
+        // max won the first two boards of the top row and is sent to the first one by min
+        let Fen(won) = "xxxxxxo2/9/9/9/9/9/9/9/9 x 3,1".parse::<Fen<GameBoard>>().unwrap();
+        assert_eq!(WeightedEvaluator::new(Weights { sub_boards: 1, ..zero }).evaluate(&won, Player::Max), 2);
+        assert_eq!(WeightedEvaluator::new(Weights { global_threats: 1, ..zero }).evaluate(&won, Player::Max), 1);
+        assert_eq!(WeightedEvaluator::new(Weights { free_choice: 1, ..zero }).evaluate(&won, Player::Max), 1);
+        assert_eq!(WeightedEvaluator::new(Weights { free_choice: 1, ..zero }).evaluate(&won, Player::Min), -1);
+        assert_eq!(WeightedEvaluator::new(Weights { local_threats: 1, centre: 1, ..zero }).evaluate(&won, Player::Max), 0);
+        assert_eq!(DefaultEvaluator.evaluate(&won, Player::Max), 2);
+        assert_eq!(WeightedEvaluator::default().evaluate(&won, Player::Max), 100 * 2 + 50 + 20);
+    }
+
+    #[test]
+    fn weighted_evaluator_finds_winning_move() {
+        // winning the third board of the top row wins the game, which the default evaluation scores no better than
+        // a single won board
+        let Fen(board) = "xxxxxxxx1/9/9/2o6/9/9/9/9/9 x 4,3".parse::<Fen<GameBoard>>().unwrap();
+        let mut strategy = Strategy::weighted(NullCache);
+        let best_moves = alpha_beta(&mut strategy, &mut board.clone(), 3);
+        assert!(best_moves.iter().all(|m| Strategy::<NullCache>::notations(&board, &m.min_max_move) == [Position { board: 2, cell: 2 }]));
+        // the search ends at the win, which is weighted by the two levels left
+        assert_eq!(best_moves[0].score, WeightedEvaluator::default().win_score() * 3);
+    }
+}